@@ -0,0 +1,95 @@
+# @generated by `cargo buckal`
+
+"""Fetch a git repository at a fixed revision, shared by the crates it contains.
+
+Unlike the `git_fetch` rule of the prelude, the submodules of the repository are checked out
+like Cargo does, and the history can be fetched in full for servers refusing shallow fetches.
+"""
+
+load("@prelude//python_bootstrap:python_bootstrap.bzl", "PythonBootstrapToolchainInfo")
+
+_FETCH_SCRIPT = """
+import argparse
+import os
+import shutil
+import subprocess
+
+parser = argparse.ArgumentParser()
+parser.add_argument("--work-tree", required=True)
+parser.add_argument("--repo", required=True)
+parser.add_argument("--rev", required=True)
+parser.add_argument("--shallow", action="store_true")
+parser.add_argument("--submodules", action="store_true")
+args = parser.parse_args()
+
+
+def git(*cmd):
+    subprocess.run(["git", "-C", args.work_tree, *cmd], check=True)
+
+
+os.makedirs(args.work_tree, exist_ok=True)
+git("init", "--quiet")
+git("remote", "add", "origin", args.repo)
+if args.shallow:
+    git("fetch", "--quiet", "--no-tags", "--depth=1", "origin", args.rev)
+    git("-c", "advice.detachedHead=false", "checkout", "--quiet", "FETCH_HEAD")
+else:
+    # The revision may not be reachable from any branch, so it is fetched by itself first, and
+    # from every branch and tag only for servers refusing to fetch a commit by its hash
+    try:
+        git("fetch", "--quiet", "--no-tags", "origin", args.rev)
+        checkout = "FETCH_HEAD"
+    except subprocess.CalledProcessError:
+        git("fetch", "--quiet", "--tags", "origin", "+refs/heads/*:refs/remotes/origin/*")
+        checkout = args.rev
+    git("-c", "advice.detachedHead=false", "checkout", "--quiet", checkout)
+if args.submodules:
+    depth = ["--depth=1"] if args.shallow else []
+    git("submodule", "update", "--init", "--recursive", "--quiet", *depth)
+
+# Only the sources are outputs, git metadata differs between fetches
+for root, dirs, files in os.walk(args.work_tree, topdown=True):
+    if ".git" in dirs:
+        dirs.remove(".git")
+        shutil.rmtree(os.path.join(root, ".git"))
+    if ".git" in files:
+        os.remove(os.path.join(root, ".git"))
+"""
+
+def _git_fetch_impl(ctx: AnalysisContext) -> list[Provider]:
+    script = ctx.actions.write("git_fetch.py", _FETCH_SCRIPT)
+    work_tree = ctx.actions.declare_output(ctx.label.name, dir = True)
+
+    cmd = [
+        ctx.attrs._python_bootstrap_toolchain[PythonBootstrapToolchainInfo].interpreter,
+        script,
+        cmd_args(work_tree.as_output(), format = "--work-tree={}"),
+        cmd_args(ctx.attrs.repo, format = "--repo={}"),
+        cmd_args(ctx.attrs.rev, format = "--rev={}"),
+    ]
+    if ctx.attrs.shallow:
+        cmd.append("--shallow")
+    if ctx.attrs.submodules:
+        cmd.append("--submodules")
+    ctx.actions.run(cmd_args(cmd), category = "git_fetch", local_only = True)
+
+    sub_targets = {
+        path: [DefaultInfo(default_output = work_tree.project(path))]
+        for path in ctx.attrs.sub_targets
+    }
+    return [DefaultInfo(default_output = work_tree, sub_targets = sub_targets)]
+
+git_fetch = rule(
+    impl = _git_fetch_impl,
+    attrs = {
+        "repo": attrs.string(),
+        "rev": attrs.string(),
+        "shallow": attrs.bool(default = True),
+        "sub_targets": attrs.list(attrs.string(), default = []),
+        "submodules": attrs.bool(default = True),
+        "_python_bootstrap_toolchain": attrs.toolchain_dep(
+            default = "toolchains//:python_bootstrap",
+            providers = [PythonBootstrapToolchainInfo],
+        ),
+    },
+)
@@ -11,6 +11,7 @@ def system_demo_rust_toolchain():
             "prelude//os/constraints:linux": "x86_64-unknown-linux-gnu",
             "prelude//os/constraints:macos": "aarch64-apple-darwin",
             "prelude//os/constraints:windows": "x86_64-pc-windows-msvc",
+            "prelude//cpu/constraints:wasm32": "wasm32-unknown-unknown",
             "DEFAULT": "x86_64-unknown-linux-gnu",
         }),
         default_edition = "2021",
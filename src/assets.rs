@@ -6,6 +6,9 @@ use include_dir::{Dir, DirEntry, include_dir};
 static TOOLCHAINS_ASSET: Dir = include_dir!("$CARGO_MANIFEST_DIR/assets/toolchains");
 static PLATFORMS_ASSET: Dir = include_dir!("$CARGO_MANIFEST_DIR/assets/platforms");
 
+/// Rule fetching the git repositories of dependencies, written next to their BUCK files
+pub const GIT_FETCH_RULE: &str = include_str!("../assets/git/git_fetch.bzl");
+
 fn normalize_line_endings(bytes: &[u8]) -> Vec<u8> {
     let mut result = Vec::with_capacity(bytes.len());
     let mut i = 0;
@@ -16,11 +16,13 @@ pub enum Rule {
     HttpArchive(HttpArchive),
     FileGroup(FileGroup),
     GitFetch(GitFetch),
+    Alias(Alias),
     CargoManifest(CargoManifest),
     RustLibrary(RustLibrary),
     RustBinary(RustBinary),
     RustTest(RustTest),
     BuildscriptRun(BuildscriptRun),
+    ConfigSetting(ConfigSetting),
 }
 
 impl Rule {
@@ -40,6 +42,7 @@ pub trait RustRule {
     fn env_mut(&mut self) -> &mut Map<String, String>;
     fn named_deps_mut(&mut self) -> &mut Map<String, String>;
     fn os_named_deps_mut(&mut self) -> &mut Map<String, Map<String, String>>;
+    fn target_compatible_with_mut(&mut self) -> &mut Set<String>;
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -48,6 +51,8 @@ pub enum CargoTargetKind {
     Bin,
     CustomBuild,
     Test,
+    Example,
+    Bench,
 }
 
 #[derive(Debug, PartialEq)]
@@ -60,7 +65,7 @@ pub struct Load {
 #[serde(rename = "http_archive")]
 pub struct HttpArchive {
     pub name: String,
-    pub urls: Set<String>,
+    pub urls: Vec<String>,
     pub sha256: String,
     #[serde(rename = "type")]
     pub _type: String,
@@ -75,6 +80,22 @@ pub struct GitFetch {
     pub name: String,
     pub repo: String,
     pub rev: String,
+    /// Fetch only the given revision instead of the whole history
+    pub shallow: bool,
+    /// Check out the submodules of the repository
+    pub submodules: bool,
+    /// Subdirectories of the checkout exposed as `[<path>]` subtargets
+    #[serde(skip_serializing_if = "Set::is_empty")]
+    pub sub_targets: Set<String>,
+    #[serde(skip_serializing_if = "Set::is_empty")]
+    pub visibility: Set<String>,
+}
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+#[serde(rename = "alias")]
+pub struct Alias {
+    pub name: String,
+    pub actual: String,
 }
 
 #[derive(Serialize, Default, Debug, PartialEq)]
@@ -89,6 +110,8 @@ pub struct CargoManifest {
 pub struct RustLibrary {
     pub name: String,
     pub srcs: Set<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub mapped_srcs: Map<String, String>,
     #[serde(rename = "crate")]
     pub crate_name: String,
     pub crate_root: String,
@@ -107,6 +130,14 @@ pub struct RustLibrary {
     pub rustc_flags: Set<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proc_macro: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doctests: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crate_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_linkage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_style: Option<String>,
     #[serde(skip_serializing_if = "Map::is_empty")]
     pub named_deps: Map<String, String>,
     #[serde(skip_serializing_if = "Map::is_empty")]
@@ -123,6 +154,8 @@ pub struct RustLibrary {
 pub struct RustBinary {
     pub name: String,
     pub srcs: Set<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub mapped_srcs: Map<String, String>,
     #[serde(rename = "crate")]
     pub crate_name: String,
     pub crate_root: String,
@@ -155,6 +188,8 @@ pub struct RustBinary {
 pub struct RustTest {
     pub name: String,
     pub srcs: Set<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub mapped_srcs: Map<String, String>,
     #[serde(rename = "crate")]
     pub crate_name: String,
     pub crate_root: String,
@@ -171,6 +206,8 @@ pub struct RustTest {
     pub features: Set<String>,
     #[serde(skip_serializing_if = "Set::is_empty")]
     pub rustc_flags: Set<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub framework: Option<bool>,
     #[serde(skip_serializing_if = "Map::is_empty")]
     pub named_deps: Map<String, String>,
     #[serde(skip_serializing_if = "Map::is_empty")]
@@ -200,6 +237,13 @@ pub struct BuildscriptRun {
     pub visibility: Set<String>,
 }
 
+#[derive(Serialize, Default, Debug, PartialEq)]
+#[serde(rename = "config_setting")]
+pub struct ConfigSetting {
+    pub name: String,
+    pub values: Map<String, String>,
+}
+
 #[derive(Default, Debug, PartialEq)]
 pub struct Glob {
     pub include: Set<String>,
@@ -213,6 +257,8 @@ pub struct FileGroup {
     pub srcs: Glob,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub out: Option<String>,
+    #[serde(skip_serializing_if = "Set::is_empty")]
+    pub visibility: Set<String>,
 }
 
 impl Serialize for Load {
@@ -432,6 +478,13 @@ impl RuleKwargs {
             .unwrap_or_default()
     }
 
+    fn get_list_ordered(&self, key: &str) -> Vec<String> {
+        match self.args.get(key).map(|expr| &expr.node) {
+            Some(ExprP::List(items)) => items.iter().filter_map(extract_string).collect(),
+            _ => Vec::new(),
+        }
+    }
+
     fn get_dict(&self, key: &str) -> Map<String, String> {
         self.args
             .get(key)
@@ -490,6 +543,10 @@ macro_rules! impl_rust_rule {
             fn os_named_deps_mut(&mut self) -> &mut Map<String, Map<String, String>> {
                 &mut self.os_named_deps
             }
+
+            fn target_compatible_with_mut(&mut self) -> &mut Set<String> {
+                &mut self.target_compatible_with
+            }
         }
     };
 }
@@ -615,6 +672,7 @@ impl RustLibrary {
     fn from_kwargs(kwargs: &RuleKwargs) -> anyhow::Result<Self> {
         let name = kwargs.get_str("name")?;
         let srcs = kwargs.get_list("srcs");
+        let mapped_srcs = kwargs.get_dict("mapped_srcs");
         let crate_name = kwargs.get_str("crate")?;
         let crate_root = kwargs.get_str("crate_root")?;
         let edition = kwargs.get_str("edition")?;
@@ -625,6 +683,10 @@ impl RustLibrary {
         let features = kwargs.get_list("features");
         let rustc_flags = kwargs.get_list("rustc_flags");
         let proc_macro = kwargs.get_bool_opt("proc_macro");
+        let doctests = kwargs.get_bool_opt("doctests");
+        let crate_type = kwargs.get_str_opt("crate_type");
+        let preferred_linkage = kwargs.get_str_opt("preferred_linkage");
+        let link_style = kwargs.get_str_opt("link_style");
         let named_deps = kwargs.get_dict("named_deps");
         let os_named_deps = kwargs.get_nested_dict("os_named_deps");
         let os_deps = kwargs.get_dict_of_lists("os_deps");
@@ -633,6 +695,7 @@ impl RustLibrary {
         Ok(RustLibrary {
             name,
             srcs,
+            mapped_srcs,
             crate_name,
             crate_root,
             edition,
@@ -643,6 +706,10 @@ impl RustLibrary {
             features,
             rustc_flags,
             proc_macro,
+            doctests,
+            crate_type,
+            preferred_linkage,
+            link_style,
             named_deps,
             os_named_deps,
             os_deps,
@@ -656,6 +723,7 @@ impl RustBinary {
     fn from_kwargs(kwargs: &RuleKwargs) -> anyhow::Result<Self> {
         let name = kwargs.get_str("name")?;
         let srcs = kwargs.get_list("srcs");
+        let mapped_srcs = kwargs.get_dict("mapped_srcs");
         let crate_name = kwargs.get_str("crate")?;
         let crate_root = kwargs.get_str("crate_root")?;
         let edition = kwargs.get_str("edition")?;
@@ -673,6 +741,7 @@ impl RustBinary {
         Ok(RustBinary {
             name,
             srcs,
+            mapped_srcs,
             crate_name,
             crate_root,
             edition,
@@ -695,6 +764,7 @@ impl RustTest {
     fn from_kwargs(kwargs: &RuleKwargs) -> anyhow::Result<Self> {
         let name = kwargs.get_str("name")?;
         let srcs = kwargs.get_list("srcs");
+        let mapped_srcs = kwargs.get_dict("mapped_srcs");
         let crate_name = kwargs.get_str("crate")?;
         let crate_root = kwargs.get_str("crate_root")?;
         let edition = kwargs.get_str("edition")?;
@@ -704,6 +774,7 @@ impl RustTest {
         let env = kwargs.get_dict("env");
         let features = kwargs.get_list("features");
         let rustc_flags = kwargs.get_list("rustc_flags");
+        let framework = kwargs.get_bool_opt("framework");
         let named_deps = kwargs.get_dict("named_deps");
         let os_named_deps = kwargs.get_nested_dict("os_named_deps");
         let os_deps = kwargs.get_dict_of_lists("os_deps");
@@ -712,6 +783,7 @@ impl RustTest {
         Ok(RustTest {
             name,
             srcs,
+            mapped_srcs,
             crate_name,
             crate_root,
             edition,
@@ -721,6 +793,7 @@ impl RustTest {
             env,
             features,
             rustc_flags,
+            framework,
             named_deps,
             os_named_deps,
             os_deps,
@@ -773,7 +846,7 @@ impl BuildscriptRun {
 impl HttpArchive {
     fn from_kwargs(kwargs: &RuleKwargs) -> anyhow::Result<Self> {
         let name = kwargs.get_str("name")?;
-        let urls = kwargs.get_list("urls");
+        let urls = kwargs.get_list_ordered("urls");
         let sha256 = kwargs.get_str("sha256")?;
         let _type = kwargs.get_str("type")?;
         let strip_prefix = kwargs.get_str("strip_prefix")?;
@@ -794,7 +867,27 @@ impl GitFetch {
         let name = kwargs.get_str("name")?;
         let repo = kwargs.get_str("repo")?;
         let rev = kwargs.get_str("rev")?;
-        Ok(GitFetch { name, repo, rev })
+        let shallow = kwargs.get_bool_opt("shallow").unwrap_or(true);
+        let submodules = kwargs.get_bool_opt("submodules").unwrap_or(true);
+        let sub_targets = kwargs.get_list("sub_targets");
+        let visibility = kwargs.get_list("visibility");
+        Ok(GitFetch {
+            name,
+            repo,
+            rev,
+            shallow,
+            submodules,
+            sub_targets,
+            visibility,
+        })
+    }
+}
+
+impl Alias {
+    fn from_kwargs(kwargs: &RuleKwargs) -> anyhow::Result<Self> {
+        let name = kwargs.get_str("name")?;
+        let actual = kwargs.get_str("actual")?;
+        Ok(Alias { name, actual })
     }
 }
 
@@ -803,7 +896,21 @@ impl FileGroup {
         let name = kwargs.get_str("name")?;
         let srcs = kwargs.get_glob("srcs")?;
         let out = kwargs.get_str_opt("out");
-        Ok(FileGroup { name, srcs, out })
+        let visibility = kwargs.get_list("visibility");
+        Ok(FileGroup {
+            name,
+            srcs,
+            out,
+            visibility,
+        })
+    }
+}
+
+impl ConfigSetting {
+    fn from_kwargs(kwargs: &RuleKwargs) -> anyhow::Result<Self> {
+        let name = kwargs.get_str("name")?;
+        let values = kwargs.get_dict("values");
+        Ok(ConfigSetting { name, values })
     }
 }
 
@@ -847,6 +954,10 @@ fn parse_rule_from_call(
             .inspect_err(|e| buckal_error!("failed to parse git_fetch: {}", e))
             .ok()
             .map(Rule::GitFetch),
+        "alias" => Alias::from_kwargs(&kwargs)
+            .inspect_err(|e| buckal_error!("failed to parse alias: {}", e))
+            .ok()
+            .map(Rule::Alias),
         "filegroup" => FileGroup::from_kwargs(&kwargs)
             .inspect_err(|e| buckal_error!("failed to parse filegroup: {}", e))
             .ok()
@@ -855,6 +966,10 @@ fn parse_rule_from_call(
             .inspect_err(|e| buckal_error!("failed to parse cargo_manifest: {}", e))
             .ok()
             .map(Rule::CargoManifest),
+        "config_setting" => ConfigSetting::from_kwargs(&kwargs)
+            .inspect_err(|e| buckal_error!("failed to parse config_setting: {}", e))
+            .ok()
+            .map(Rule::ConfigSetting),
         _ => None,
     }
 }
@@ -865,11 +980,13 @@ fn rule_map_key(rule: &Rule) -> String {
         Rule::HttpArchive(r) => format!("http_archive[{}]", r.name),
         Rule::FileGroup(r) => format!("filegroup[{}]", r.name),
         Rule::GitFetch(r) => format!("git_fetch[{}]", r.name),
+        Rule::Alias(r) => format!("alias[{}]", r.name),
         Rule::CargoManifest(r) => format!("cargo_manifest[{}]", r.name),
         Rule::RustLibrary(r) => format!("rust_library[{}]", r.name),
         Rule::RustBinary(r) => format!("rust_binary[{}]", r.name),
         Rule::RustTest(r) => format!("rust_test[{}]", r.name),
         Rule::BuildscriptRun(r) => format!("buildscript_run[{}]", r.name),
+        Rule::ConfigSetting(r) => format!("config_setting[{}]", r.name),
     }
 }
 
@@ -1129,6 +1246,7 @@ mod tests {
                     exclude: Set::new(),
                 },
                 out: None,
+                visibility: Set::new(),
             }),
             Rule::CargoManifest(CargoManifest {
                 name: "manifest".to_string(),
@@ -1368,9 +1486,9 @@ mod tests {
             }),
             Rule::HttpArchive(HttpArchive {
                 name: "vendor".to_string(),
-                urls: Set::from([
+                urls: vec![
                     "https://static.crates.io/crates/aws-lc-rs/aws-lc-rs-1.15.4.crate".to_string(),
-                ]),
+                ],
                 sha256: "7b7b6141e96a8c160799cc2d5adecd5cbbe5054cb8c7c4af53da0f83bb7ad256"
                     .to_string(),
                 _type: "tar.gz".to_string(),
@@ -1485,10 +1603,9 @@ mod tests {
                 bzl: "@buckal//:wrapper.bzl".to_string(),
                 items: Set::from(["rust_library".to_string()]),
             }),
-            Rule::GitFetch(GitFetch {
+            Rule::Alias(Alias {
                 name: "vendor".to_string(),
-                repo: "https://github.com/web3infra-foundation/git-internal.git".to_string(),
-                rev: "65b910d7571f36aa231958992e005a7a1c0838e9".to_string(),
+                actual: "//third-party/rust/git/_repos/9c150773e8e7cf19:vendor".to_string(),
             }),
             Rule::CargoManifest(CargoManifest {
                 name: "manifest".to_string(),
@@ -1597,7 +1714,7 @@ mod tests {
         assert_eq!(rules.len(), 1);
         let expected = Rule::HttpArchive(HttpArchive {
             name: "example_archive".to_string(),
-            urls: Set::from(["https://example.com/archive.tar.gz".to_string()]),
+            urls: vec!["https://example.com/archive.tar.gz".to_string()],
             sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string(),
             _type: "tar.gz".to_string(),
             strip_prefix: "archive".to_string(),
@@ -1629,6 +1746,7 @@ mod tests {
                 exclude: Set::from(["examples/exclude/**".to_string()]),
             },
             out: Some("example_out".to_string()),
+            visibility: Set::new(),
         });
         let actual = rules
             .get(&rule_map_key(&expected))
@@ -1649,6 +1767,10 @@ mod tests {
             name: "example_repo".to_string(),
             repo: "https://example.com/repo.git".to_string(),
             rev: "abcdef1234567890".to_string(),
+            shallow: false,
+            submodules: true,
+            sub_targets: Set::from(["tokio-util".to_string()]),
+            visibility: Set::from(["PUBLIC".to_string()]),
         });
         let actual = rules
             .get(&rule_map_key(&expected))
@@ -1678,6 +1800,25 @@ mod tests {
         );
     }
 
+    /// Test parsing a BUCK file with a `config_setting` rule that includes all possible fields.
+    #[test]
+    fn test_parsing_single_config_setting() {
+        let rules = parse_buck_file(get_test_file("single_config_setting.BUCK"))
+            .expect("parse should succeed");
+        assert_eq!(rules.len(), 1);
+        let expected = Rule::ConfigSetting(ConfigSetting {
+            name: "feature-serde".to_string(),
+            values: Map::from([("cargo_features.serde".to_string(), "true".to_string())]),
+        });
+        let actual = rules
+            .get(&rule_map_key(&expected))
+            .expect("config_setting rule should be present");
+        assert_eq!(
+            actual, &expected,
+            "parsed config_setting rule should match expected"
+        );
+    }
+
     /// Test parsing a BUCK file with a `rust_library` rule that includes all possible fields.
     #[test]
     fn test_parsing_single_rust_library() {
@@ -1687,6 +1828,7 @@ mod tests {
         let expected = Rule::RustLibrary(RustLibrary {
             name: "example_lib".to_string(),
             srcs: Set::from(["src/lib.rs".to_string()]),
+            mapped_srcs: Map::new(),
             crate_name: "example_lib".to_string(),
             crate_root: "src/lib.rs".to_string(),
             edition: "2024".to_string(),
@@ -1697,6 +1839,10 @@ mod tests {
             features: Set::from(["default".to_string()]),
             rustc_flags: Set::from(["@$(location :manifest[env_flags])".to_string()]),
             proc_macro: Some(true),
+            doctests: Some(true),
+            crate_type: Some("cdylib".to_string()),
+            preferred_linkage: Some("shared".to_string()),
+            link_style: Some("static_pic".to_string()),
             named_deps: Map::from([("serde".to_string(), ":serde_dep".to_string())]),
             os_named_deps: Map::from([(
                 "win_dep".to_string(),
@@ -1724,6 +1870,7 @@ mod tests {
         let expected = Rule::RustBinary(RustBinary {
             name: "example_bin".to_string(),
             srcs: Set::from(["src/main.rs".to_string()]),
+            mapped_srcs: Map::new(),
             crate_name: "example_bin".to_string(),
             crate_root: "src/main.rs".to_string(),
             edition: "2024".to_string(),
@@ -1760,6 +1907,7 @@ mod tests {
         let expected = Rule::RustTest(RustTest {
             name: "example_test".to_string(),
             srcs: Set::from(["src/lib.rs".to_string()]),
+            mapped_srcs: Map::new(),
             crate_name: "example_test".to_string(),
             crate_root: "src/lib.rs".to_string(),
             edition: "2024".to_string(),
@@ -1769,6 +1917,7 @@ mod tests {
             env: Map::from([("RUST_LOG".to_string(), "debug".to_string())]),
             features: Set::from(["default".to_string()]),
             rustc_flags: Set::from(["@$(location :manifest[env_flags])".to_string()]),
+            framework: Some(false),
             deps: Set::from([":dep".to_string()]),
             os_deps: Map::from([("linux".to_string(), Set::from([":linux_dep".to_string()]))]),
             named_deps: Map::from([("serde".to_string(), ":serde_dep".to_string())]),
@@ -1,9 +1,19 @@
 mod actions;
+mod cargo_config;
 mod cross;
 mod deps;
 mod emit;
+mod features;
+mod fixups;
+mod lints;
+mod manifest;
+mod profiles;
 mod rules;
+mod select;
 mod windows;
 
 pub use actions::flush_root;
-pub use rules::{buckify_dep_node, buckify_root_node, gen_buck_content, vendor_package};
+pub use rules::{
+    buckify_dep_node, buckify_root_node, gen_buck_content, get_bench_name, get_bin_test_name,
+    vendor_package,
+};
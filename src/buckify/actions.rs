@@ -1,6 +1,5 @@
 use cargo_metadata::{Package, camino::Utf8Path};
 use cargo_util_schemas::core::PackageIdSpec;
-use regex::Regex;
 
 use crate::{
     buck::{Rule, parse_buck_file, patch_buck_rules},
@@ -11,17 +10,25 @@ use crate::{
 };
 
 use super::{
-    buckify_dep_node, buckify_root_node, cross, gen_buck_content, vendor_package, windows,
+    buckify_dep_node, buckify_root_node, cargo_config, cross, features, gen_buck_content, lints,
+    profiles, vendor_package, windows,
 };
 
 impl BuckalChange {
     pub fn apply(&self, ctx: &BuckalContext) {
         // This function applies changes to the BUCK files of detected packages in the cache diff, but skips the root package.
-        let re: Regex = Regex::new(r"^([^+#]+)\+([^#]+)#([^@]+)@([^+#]+)(?:\+(.+))?$")
-            .expect("error creating regex");
         let skip_pattern = format!("path+file://{}", ctx.workspace_root);
 
-        for (id, change_type) in &self.changes {
+        // Path packages outside of the project are copied into it, and their sources may change
+        // without changing the resolve, so they are flushed on every run
+        let path_packages = ctx
+            .packages_map
+            .values()
+            .filter(|p| p.source.is_none() && !self.changes.contains_key(&p.id))
+            .filter(|p| is_third_party(p))
+            .map(|p| (&p.id, &ChangeType::Changed));
+
+        for (id, change_type) in self.changes.iter().chain(path_packages) {
             match change_type {
                 ChangeType::Added | ChangeType::Changed => {
                     // Skip root package
@@ -47,7 +54,7 @@ impl BuckalChange {
                         let vendor_dir = if !is_third_party(package) {
                             package.manifest_path.parent().unwrap().to_owned()
                         } else {
-                            vendor_package(package)
+                            vendor_package(package, ctx)
                         };
 
                         // Generate BUCK rules
@@ -57,13 +64,34 @@ impl BuckalChange {
                             buckify_dep_node(node, ctx)
                         };
 
+                        cargo_config::set_config_env(&mut buck_rules, ctx);
+
                         // Patch BUCK Rules
                         let buck_path = vendor_dir.join("BUCK");
                         merge_rules(&buck_path, &mut buck_rules, ctx);
 
                         // Generate the BUCK file
                         let mut buck_content = gen_buck_content(&buck_rules);
+                        buck_content =
+                            features::patch_platform_features(buck_content, &buck_rules, node, ctx);
+                        if !is_third_party(package) {
+                            buck_content = features::patch_feature_selects(buck_content, node, ctx);
+                            buck_content =
+                                lints::patch_lint_flags(buck_content, &buck_rules, node, ctx);
+                        }
+                        buck_content =
+                            profiles::patch_profile_flags(buck_content, &buck_rules, node, ctx);
+                        buck_content =
+                            cargo_config::patch_config_flags(buck_content, &buck_rules, node, ctx);
                         buck_content = cross::patch_rust_test_target_compatible_with(buck_content);
+                        if !is_third_party(package) {
+                            buck_content = features::patch_required_features(
+                                buck_content,
+                                &buck_rules,
+                                node,
+                                ctx,
+                            );
+                        }
                         std::fs::write(&buck_path, buck_content)
                             .expect("Failed to write BUCK file");
                     }
@@ -74,11 +102,17 @@ impl BuckalChange {
                         continue;
                     }
 
-                    let caps = re.captures(&id.repr).expect("Failed to parse package ID");
-                    let name = &caps[3];
-                    let version = &caps[4];
-
-                    buckal_log!("Removing", format!("{} v{}", name, version));
+                    // Path package IDs omit the name when it matches the directory name
+                    let package_id_spec = PackageIdSpec::parse(&id.repr)
+                        .unwrap_or_exit_ctx("failed to parse package ID");
+                    buckal_log!(
+                        "Removing",
+                        format!(
+                            "{} v{}",
+                            package_id_spec.name(),
+                            package_id_spec.version().unwrap()
+                        )
+                    );
                     let vendor_dir =
                         get_vendor_dir(id).unwrap_or_exit_ctx("failed to get vendor directory");
                     if vendor_dir.exists() {
@@ -114,14 +148,21 @@ pub fn flush_root(ctx: &BuckalContext) {
 
         // Generate BUCK rules
         let mut buck_rules = buckify_root_node(root_node, ctx);
+        cargo_config::set_config_env(&mut buck_rules, ctx);
 
         // Patch BUCK Rules
         merge_rules(&buck_path, &mut buck_rules, ctx);
 
         // Generate the BUCK file
         let mut buck_content = gen_buck_content(&buck_rules);
+        buck_content = features::patch_platform_features(buck_content, &buck_rules, root_node, ctx);
+        buck_content = features::patch_feature_selects(buck_content, root_node, ctx);
         buck_content = windows::patch_root_windows_rustc_flags(buck_content, ctx, root);
+        buck_content = lints::patch_lint_flags(buck_content, &buck_rules, root_node, ctx);
+        buck_content = profiles::patch_profile_flags(buck_content, &buck_rules, root_node, ctx);
+        buck_content = cargo_config::patch_config_flags(buck_content, &buck_rules, root_node, ctx);
         buck_content = cross::patch_rust_test_target_compatible_with(buck_content);
+        buck_content = features::patch_required_features(buck_content, &buck_rules, root_node, ctx);
         std::fs::write(&buck_path, buck_content).expect("Failed to write BUCK file");
     }
 }
@@ -133,8 +174,8 @@ pub(super) fn is_third_party(package: &Package) -> bool {
     } else {
         let package_id_spec =
             PackageIdSpec::parse(&package.id.repr).unwrap_or_exit_ctx("failed to parse package ID");
-        let buck2_root = get_buck2_root().unwrap_or_exit_ctx("failed to get Buck2 root");
         if let Some(url) = package_id_spec.url() {
+            let buck2_root = get_buck2_root().unwrap_or_exit_ctx("failed to get Buck2 root");
             let url_path = get_url_path(url);
             url_path.strip_prefix(buck2_root.as_str()).is_none()
         } else {
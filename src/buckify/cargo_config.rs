@@ -0,0 +1,165 @@
+use std::collections::BTreeMap as Map;
+
+use cargo_metadata::Node;
+
+use crate::{buck::Rule, context::BuckalContext, platform::target_setting};
+
+use super::{
+    profiles::target_rules,
+    select::{append_select_to_attr, build_select, build_string_list, pretty_print_expr},
+};
+
+/// Set the `[env]` variables of the Cargo configuration on the rules of the package.
+///
+/// Variables set by Cargo itself cannot be overridden, so existing values are kept.
+pub(super) fn set_config_env(buck_rules: &mut [Rule], ctx: &BuckalContext) {
+    for rule in buck_rules {
+        let env = match rule {
+            Rule::RustLibrary(r) => &mut r.env,
+            Rule::RustBinary(r) => &mut r.env,
+            Rule::RustTest(r) => &mut r.env,
+            Rule::BuildscriptRun(r) => &mut r.env,
+            _ => continue,
+        };
+        for (name, value) in &ctx.cargo_config.env {
+            env.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// Render the flags of each target triple, as a `select()` on the `//platforms:target-<triple>`
+/// settings if they differ between triples.
+fn render_platform_flags(flags: &Map<&str, Vec<String>>, default: &[String]) -> Option<String> {
+    if flags.values().all(|f| f == default) {
+        if default.is_empty() {
+            return None;
+        }
+        let mut out = String::new();
+        pretty_print_expr(&build_string_list(default), &mut out, 4);
+        return Some(out);
+    }
+
+    let settings: Vec<(String, &Vec<String>)> = flags
+        .iter()
+        .filter(|(_, f)| *f != default)
+        .map(|(triple, f)| (target_setting(triple), f))
+        .collect();
+    let lists: Vec<(&str, _)> = settings
+        .iter()
+        .map(|(setting, f)| (setting.as_str(), build_string_list(f)))
+        .chain(std::iter::once(("DEFAULT", build_string_list(default))))
+        .collect();
+    let mut out = String::new();
+    pretty_print_expr(&build_select(&lists), &mut out, 4);
+    Some(out)
+}
+
+/// Patch the `rustc_flags` of the rules in the BUCK content of the package with the `rustflags`
+/// and `linker` settings of the Cargo configuration.
+pub(super) fn patch_config_flags(
+    mut buck_content: String,
+    buck_rules: &[Rule],
+    node: &Node,
+    ctx: &BuckalContext,
+) -> String {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let config = &ctx.cargo_config;
+
+    let rustflags = config.platform_rustflags();
+    let mut link_flags = rustflags.clone();
+    for (triple, linker) in config.platform_linkers() {
+        link_flags
+            .entry(triple)
+            .or_default()
+            .push(format!("-Clinker={}", linker));
+    }
+
+    let compile_expr = render_platform_flags(&rustflags, &config.rustflags);
+    let link_expr = render_platform_flags(&link_flags, &config.rustflags);
+
+    // Executables and native library crate types get the linker, Rust libraries are only compiled
+    let linked_libraries: Vec<&str> = buck_rules
+        .iter()
+        .filter_map(|rule| match rule {
+            Rule::RustLibrary(r)
+                if matches!(
+                    r.crate_type.as_deref(),
+                    Some("cdylib" | "dylib" | "staticlib")
+                ) =>
+            {
+                Some(r.name.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    for (kind, name) in target_rules(buck_rules, package) {
+        let select_expr = if kind == "rust_library" && !linked_libraries.contains(&name) {
+            &compile_expr
+        } else {
+            &link_expr
+        };
+        if let Some(select_expr) = select_expr {
+            buck_content =
+                append_select_to_attr(&buck_content, kind, Some(name), "rustc_flags", select_expr);
+        }
+    }
+    buck_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_render_platform_flags() {
+        let build = vec!["--cfg".to_owned(), "build".to_owned()];
+        let same = Map::from([
+            ("x86_64-unknown-linux-gnu", build.clone()),
+            ("aarch64-apple-darwin", build.clone()),
+        ]);
+        assert_eq!(
+            render_platform_flags(&same, &build).unwrap(),
+            indoc! {r#"
+                [
+                        "--cfg",
+                        "build",
+                    ]"#}
+        );
+        assert_eq!(render_platform_flags(&Map::new(), &[]), None);
+
+        let linux = Map::from([
+            (
+                "x86_64-unknown-linux-gnu",
+                vec!["-Clinker=clang".to_owned()],
+            ),
+            ("aarch64-apple-darwin", vec![]),
+        ]);
+        let content = append_select_to_attr(
+            indoc! {r#"
+                rust_binary(
+                    name = "demo",
+                    rustc_flags = ["@$(location :manifest[env_flags])"],
+                )
+            "#},
+            "rust_binary",
+            Some("demo"),
+            "rustc_flags",
+            &render_platform_flags(&linux, &[]).unwrap(),
+        );
+        assert_eq!(
+            content,
+            indoc! {r#"
+                rust_binary(
+                    name = "demo",
+                    rustc_flags = ["@$(location :manifest[env_flags])"] + select({
+                        "//platforms:target-x86_64-unknown-linux-gnu": [
+                            "-Clinker=clang",
+                        ],
+                        "DEFAULT": [],
+                    }),
+                )
+            "#}
+        );
+    }
+}
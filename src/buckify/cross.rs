@@ -61,7 +61,7 @@ fn find_rust_test_call(expr: &AstExpr) -> Option<usize> {
     None
 }
 
-fn call_has_arg(
+pub(super) fn call_has_arg(
     args: &[starlark_syntax::codemap::Spanned<ArgumentP<AstNoPayload>>],
     name: &str,
 ) -> bool {
@@ -74,7 +74,7 @@ fn call_has_arg(
     })
 }
 
-fn insert_pos_before_closing_paren(span: Span) -> Option<usize> {
+pub(super) fn insert_pos_before_closing_paren(span: Span) -> Option<usize> {
     let end = span.end().get() as usize;
     end.checked_sub(1)
 }
@@ -92,7 +92,7 @@ fn build_insert(content: &str, insert_pos: usize) -> String {
     out
 }
 
-fn needs_leading_comma(content: &str, insert_pos: usize) -> bool {
+pub(super) fn needs_leading_comma(content: &str, insert_pos: usize) -> bool {
     for ch in content[..insert_pos].chars().rev() {
         if ch.is_whitespace() {
             continue;
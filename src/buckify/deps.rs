@@ -1,4 +1,7 @@
-use std::{collections::BTreeSet as Set, path::PathBuf};
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    path::PathBuf,
+};
 
 use anyhow::{Context, Result, bail};
 use cargo_metadata::{DependencyKind, Node, NodeDep, Package, Target};
@@ -9,14 +12,17 @@ use crate::{
     buckify::actions::is_third_party,
     context::BuckalContext,
     platform::{Os, oses_from_platform, platform_is_target_only},
-    utils::{get_buck2_root, get_vendor_path_relative},
+    utils::{get_buck2_root, get_vendor_path_relative, load_manifest, unmet_required_features},
 };
 
+use super::emit::get_exec_name;
+use super::manifest::artifact_dependencies;
+
 pub(super) fn dep_kind_matches(target_kind: CargoTargetKind, dep_kind: DependencyKind) -> bool {
     match target_kind {
         CargoTargetKind::CustomBuild => dep_kind == DependencyKind::Build,
-        // Cargo test targets can depend on both dev-deps and regular deps.
-        CargoTargetKind::Test => {
+        // Cargo test, example and bench targets can depend on both dev-deps and regular deps.
+        CargoTargetKind::Test | CargoTargetKind::Example | CargoTargetKind::Bench => {
             dep_kind == DependencyKind::Development || dep_kind == DependencyKind::Normal
         }
         _ => dep_kind == DependencyKind::Normal,
@@ -38,7 +44,8 @@ fn get_lib_targets(package: &Package) -> Vec<&Target> {
         .collect()
 }
 
-fn resolve_first_party_label(dep_package: &Package) -> Result<String> {
+/// Resolve the Buck package path (e.g. `//crates/foo`) of a first-party dependency
+fn resolve_first_party_path(dep_package: &Package) -> Result<String> {
     let buck2_root = get_buck2_root().context("failed to get buck2 root")?;
     let manifest_path = PathBuf::from(&dep_package.manifest_path);
     let manifest_dir = manifest_path
@@ -57,6 +64,12 @@ fn resolve_first_party_label(dep_package: &Package) -> Result<String> {
         // Normalize path separators for Buck2 (always use forward slashes)
         .replace('\\', "/");
 
+    Ok(format!("//{relative_path}"))
+}
+
+fn resolve_first_party_label(dep_package: &Package) -> Result<String> {
+    let package_path = resolve_first_party_path(dep_package)?;
+
     let dep_bin_targets: Vec<_> = dep_package
         .targets
         .iter()
@@ -75,7 +88,7 @@ fn resolve_first_party_label(dep_package: &Package) -> Result<String> {
 
     let buckal_name = resolve_buckal_name(&dep_bin_targets, &dep_lib_targets);
 
-    Ok(format!("//{relative_path}:{buckal_name}"))
+    Ok(format!("{package_path}:{buckal_name}"))
 }
 
 fn resolve_buckal_name(dep_bin_targets: &[&Target], dep_lib_targets: &[&Target]) -> String {
@@ -89,6 +102,144 @@ fn resolve_buckal_name(dep_bin_targets: &[&Target], dep_lib_targets: &[&Target])
     }
 }
 
+/// Get the binary targets of a package without a library, which can only be an artifact dependency
+fn get_artifact_bin_targets(dep_package: &Package) -> Vec<&Target> {
+    if !get_lib_targets(dep_package).is_empty() {
+        return vec![];
+    }
+    dep_package
+        .targets
+        .iter()
+        .filter(|t| t.kind.contains(&cargo_metadata::TargetKind::Bin))
+        .collect()
+}
+
+/// Whether the dependency on `dep_package` is an artifact dependency, and if so whether its
+/// library is a dependency too.
+///
+/// Packages without a library can only be artifact dependencies, the others are declared with
+/// `artifact = "bin"` in the manifest of the dependent package.
+fn artifact_kind(dep_package: &Package, artifacts: &Map<String, bool>) -> Option<bool> {
+    if !get_artifact_bin_targets(dep_package).is_empty() {
+        return Some(false);
+    }
+    artifacts.get(dep_package.name.as_str()).copied()
+}
+
+/// Whether a package with a library is an artifact dependency of another package, whose
+/// binaries then need rules next to the library.
+pub(super) fn is_artifact_dependency(package: &Package, ctx: &BuckalContext) -> bool {
+    ctx.nodes_map
+        .values()
+        .filter(|node| node.deps.iter().any(|dep| dep.pkg == package.id))
+        .any(|node| load_artifact_dependencies(node, ctx).contains_key(package.name.as_str()))
+}
+
+/// Get the name of the rule of a binary of a third-party package.
+///
+/// The library rule is named after the package, so a binary of the same name gets a suffix.
+pub(super) fn get_artifact_bin_name(package: &Package, bin_target: &Target) -> String {
+    if bin_target.name == package.name.as_str() && !get_lib_targets(package).is_empty() {
+        format!("{}-bin", bin_target.name)
+    } else {
+        bin_target.name.to_owned()
+    }
+}
+
+fn load_artifact_dependencies(node: &Node, ctx: &BuckalContext) -> Map<String, bool> {
+    ctx.packages_map
+        .get(&node.id)
+        .and_then(|package| load_manifest(&package.manifest_path).ok())
+        .map(|manifest| artifact_dependencies(&manifest))
+        .unwrap_or_default()
+}
+
+/// Get the binary an artifact dependency refers to without naming one.
+///
+/// Cargo prefers the binary named like the package, otherwise there has to be exactly one binary.
+fn resolve_artifact_name<'a>(dep_package: &Package, bin_targets: &[&'a Target]) -> Option<&'a str> {
+    match bin_targets {
+        [bin] => Some(bin.name.as_str()),
+        _ => bin_targets
+            .iter()
+            .find(|b| b.name == dep_package.name.as_str())
+            .map(|b| b.name.as_str()),
+    }
+}
+
+/// Get the `CARGO_BIN_FILE_*` variables locating the binaries of the artifact dependencies of the
+/// given target kind.
+///
+/// Build scripts read them when they run, so they belong to the `buildscript_run` rule, while
+/// other targets read them at compile time.
+pub(super) fn artifact_env(
+    node: &Node,
+    kind: CargoTargetKind,
+    ctx: &BuckalContext,
+) -> Result<Map<String, String>> {
+    collect_artifact_env(node, kind, &load_artifact_dependencies(node, ctx), ctx)
+}
+
+fn collect_artifact_env(
+    node: &Node,
+    kind: CargoTargetKind,
+    artifacts: &Map<String, bool>,
+    ctx: &BuckalContext,
+) -> Result<Map<String, String>> {
+    let mut env = Map::new();
+    for dep in &node.deps {
+        let Some(dep_package) = ctx.packages_map.get(&dep.pkg) else {
+            continue;
+        };
+        if artifact_kind(dep_package, artifacts).is_none()
+            || !dep
+                .dep_kinds
+                .iter()
+                .any(|dk| dep_kind_matches(kind, dk.kind))
+        {
+            continue;
+        }
+
+        let package_path = if !is_third_party(dep_package) {
+            resolve_first_party_path(dep_package)?
+        } else {
+            format!("//{}", get_vendor_path_relative(&dep_package.id)?)
+        };
+
+        // Binaries whose `required-features` are not enabled get no rules
+        let features = ctx
+            .nodes_map
+            .get(&dep.pkg)
+            .map(|n| n.features.as_slice())
+            .unwrap_or_default();
+        let bin_targets: Vec<&Target> = dep_package
+            .targets
+            .iter()
+            .filter(|t| t.kind.contains(&cargo_metadata::TargetKind::Bin))
+            .filter(|t| unmet_required_features(t, features).is_empty())
+            .collect();
+
+        let dep_env_name = dep.name.to_uppercase().replace('-', "_");
+        let default_bin = resolve_artifact_name(dep_package, &bin_targets);
+        for bin in bin_targets {
+            let bin_name = if is_third_party(dep_package) {
+                get_artifact_bin_name(dep_package, bin)
+            } else {
+                bin.name.to_owned()
+            };
+            let location = format!("$(location {}:{})", package_path, bin_name);
+            if default_bin == Some(bin.name.as_str()) {
+                env.insert(format!("CARGO_BIN_FILE_{}", dep_env_name), location.clone());
+            }
+            env.insert(
+                format!("CARGO_BIN_FILE_{}_{}", dep_env_name, bin.name),
+                location,
+            );
+        }
+    }
+    Ok(env)
+}
+
 fn resolve_dep_label(dep: &NodeDep, dep_package: &Package) -> Result<(String, Option<String>)> {
     let dep_package_name = dep_package.name.to_string();
     let is_renamed = dep.name != dep_package_name.replace("-", "_");
@@ -198,16 +349,34 @@ fn insert_dep(
     Ok(())
 }
 
+/// Insert the dependencies of the given target kind of `node` into `rust_rule`.
+///
+/// `exec` tells whether the rule is built for the exec platform (build scripts, proc-macros and
+/// their dependencies), in which case third-party dependencies resolve to their exec variant when
+/// they have one.
 pub(super) fn set_deps(
     rust_rule: &mut dyn RustRule,
     node: &Node,
     kind: CargoTargetKind,
+    exec: bool,
     ctx: &BuckalContext,
 ) -> Result<()> {
+    let artifacts = load_artifact_dependencies(node, ctx);
+    // Build scripts get the binaries of their artifact dependencies when they run instead
+    if kind != CargoTargetKind::CustomBuild {
+        rust_rule
+            .env_mut()
+            .extend(collect_artifact_env(node, kind, &artifacts, ctx)?);
+    }
+
     for dep in &node.deps {
         let Some(dep_package) = ctx.packages_map.get(&dep.pkg) else {
             continue;
         };
+        // Binaries are not linked, only the library of artifact dependencies with `lib = true`
+        if artifact_kind(dep_package, &artifacts) == Some(false) {
+            continue;
+        }
 
         let mut unconditional = false;
         let mut platforms = Set::<Os>::new();
@@ -246,12 +415,15 @@ pub(super) fn set_deps(
             continue;
         }
 
-        let (target_label, alias) = resolve_dep_label(dep, dep_package).with_context(|| {
+        let (mut target_label, alias) = resolve_dep_label(dep, dep_package).with_context(|| {
             format!(
                 "failed to resolve dependency label for '{}' (package '{}')",
                 dep.name, dep_package.name
             )
         })?;
+        if exec && ctx.exec_features.contains_key(&dep.pkg) && is_third_party(dep_package) {
+            target_label = get_exec_name(&target_label);
+        }
 
         if unconditional {
             insert_dep(rust_rule, &target_label, alias.as_deref(), None)?;
@@ -265,6 +437,7 @@ pub(super) fn set_deps(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::mock_context;
     use cargo_metadata::TargetKind;
 
     fn mock_target(name: &str, kind: TargetKind) -> Target {
@@ -306,4 +479,196 @@ mod tests {
         let name = resolve_buckal_name(&bin_targets, &lib_targets);
         assert_eq!(name, "foo");
     }
+
+    #[test]
+    fn test_artifact_env() {
+        let id = "registry+https://github.com/rust-lang/crates.io-index#protoc-gen-prost@0.4.0";
+        let mut gated_bin = mock_target("protoc-gen-prost-gated", TargetKind::Bin);
+        gated_bin.required_features = vec!["gated".to_owned()];
+        let dep_package: Package = serde_json::from_value(serde_json::json!({
+            "name": "protoc-gen-prost",
+            "version": "0.4.0",
+            "id": id,
+            "source": "registry+https://github.com/rust-lang/crates.io-index",
+            "dependencies": [],
+            "targets": [
+                mock_target("protoc-gen-prost", TargetKind::Bin),
+                mock_target("protoc-gen-prost-crate", TargetKind::Bin),
+                gated_bin,
+            ],
+            "features": {},
+            "manifest_path": "/tmp/Cargo.toml",
+            "edition": "2021"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": "registry+https://github.com/rust-lang/crates.io-index#demo@0.1.0",
+            "deps": [{
+                "name": "protoc-gen-prost",
+                "pkg": id,
+                "dep_kinds": [{ "kind": "build", "target": null }]
+            }],
+            "dependencies": [id],
+            "features": []
+        }))
+        .unwrap();
+        let ctx = BuckalContext {
+            packages_map: std::collections::HashMap::from([(dep_package.id.clone(), dep_package)]),
+            ..mock_context()
+        };
+
+        // The binaries are located when the build script runs, not when it is compiled
+        let mut buildscript_build = crate::buck::RustBinary::default();
+        set_deps(
+            &mut buildscript_build,
+            &node,
+            CargoTargetKind::CustomBuild,
+            true,
+            &ctx,
+        )
+        .unwrap();
+        assert!(buildscript_build.deps.is_empty());
+        assert!(buildscript_build.env.is_empty());
+
+        let package_path = "//third-party/rust/crates/protoc-gen-prost/0.4.0";
+        assert_eq!(
+            artifact_env(&node, CargoTargetKind::CustomBuild, &ctx).unwrap(),
+            Map::from([
+                (
+                    "CARGO_BIN_FILE_PROTOC_GEN_PROST".to_owned(),
+                    format!("$(location {package_path}:protoc-gen-prost)")
+                ),
+                (
+                    "CARGO_BIN_FILE_PROTOC_GEN_PROST_protoc-gen-prost".to_owned(),
+                    format!("$(location {package_path}:protoc-gen-prost)")
+                ),
+                (
+                    "CARGO_BIN_FILE_PROTOC_GEN_PROST_protoc-gen-prost-crate".to_owned(),
+                    format!("$(location {package_path}:protoc-gen-prost-crate)")
+                ),
+            ])
+        );
+        assert!(
+            artifact_env(&node, CargoTargetKind::Lib, &ctx)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_artifact_env_with_library() {
+        let id = "registry+https://github.com/rust-lang/crates.io-index#protoc-gen-demo@1.0.0";
+        let dep_package: Package = serde_json::from_value(serde_json::json!({
+            "name": "protoc-gen-demo",
+            "version": "1.0.0",
+            "id": id,
+            "source": "registry+https://github.com/rust-lang/crates.io-index",
+            "dependencies": [],
+            "targets": [
+                mock_target("protoc_gen_demo", TargetKind::Lib),
+                mock_target("protoc-gen-demo", TargetKind::Bin),
+            ],
+            "features": {},
+            "manifest_path": "/tmp/Cargo.toml",
+            "edition": "2021"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": "registry+https://github.com/rust-lang/crates.io-index#demo@0.1.0",
+            "deps": [{
+                "name": "protoc_gen_demo",
+                "pkg": id,
+                "dep_kinds": [{ "kind": "build", "target": null }]
+            }],
+            "dependencies": [id],
+            "features": []
+        }))
+        .unwrap();
+        let ctx = BuckalContext {
+            packages_map: std::collections::HashMap::from([(dep_package.id.clone(), dep_package)]),
+            ..mock_context()
+        };
+
+        // The binary named like the package is renamed to leave its name to the library
+        let artifacts = Map::from([("protoc-gen-demo".to_owned(), false)]);
+        let location =
+            "$(location //third-party/rust/crates/protoc-gen-demo/1.0.0:protoc-gen-demo-bin)";
+        assert_eq!(
+            collect_artifact_env(&node, CargoTargetKind::CustomBuild, &artifacts, &ctx).unwrap(),
+            Map::from([
+                (
+                    "CARGO_BIN_FILE_PROTOC_GEN_DEMO".to_owned(),
+                    location.to_owned()
+                ),
+                (
+                    "CARGO_BIN_FILE_PROTOC_GEN_DEMO_protoc-gen-demo".to_owned(),
+                    location.to_owned()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_set_deps_exec_variant() {
+        let id = "registry+https://github.com/rust-lang/crates.io-index#memchr@2.7.4";
+        let dep_package: Package = serde_json::from_value(serde_json::json!({
+            "name": "memchr",
+            "version": "2.7.4",
+            "id": id,
+            "source": "registry+https://github.com/rust-lang/crates.io-index",
+            "dependencies": [],
+            "targets": [mock_target("memchr", TargetKind::Lib)],
+            "features": {},
+            "manifest_path": "/tmp/Cargo.toml",
+            "edition": "2021"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": "registry+https://github.com/rust-lang/crates.io-index#demo@0.1.0",
+            "deps": [{
+                "name": "memchr",
+                "pkg": id,
+                "dep_kinds": [
+                    { "kind": null, "target": null },
+                    { "kind": "build", "target": null }
+                ]
+            }],
+            "dependencies": [id],
+            "features": []
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map: std::collections::HashMap::from([(dep_package.id.clone(), dep_package)]),
+            exec_features: std::collections::HashMap::from([(
+                node.deps[0].pkg.clone(),
+                crate::context::ExecFeatures {
+                    target: Set::new(),
+                    exec: Set::from(["std".to_owned()]),
+                },
+            )]),
+            ..mock_context()
+        };
+
+        let mut rust_library = crate::buck::RustLibrary::default();
+        set_deps(&mut rust_library, &node, CargoTargetKind::Lib, false, &ctx).unwrap();
+        assert_eq!(
+            rust_library.deps,
+            Set::from(["//third-party/rust/crates/memchr/2.7.4:memchr".to_owned()])
+        );
+
+        let mut buildscript_build = crate::buck::RustBinary::default();
+        set_deps(
+            &mut buildscript_build,
+            &node,
+            CargoTargetKind::CustomBuild,
+            true,
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(
+            buildscript_build.deps,
+            Set::from(["//third-party/rust/crates/memchr/2.7.4:memchr-exec".to_owned()])
+        );
+    }
 }
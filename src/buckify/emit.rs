@@ -1,19 +1,31 @@
-use std::{borrow::Cow, collections::BTreeSet as Set};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap as Map, BTreeSet as Set},
+};
 
-use cargo_metadata::{Node, Package, Target, camino::Utf8PathBuf};
-use cargo_util_schemas::lockfile::TomlLockfileSourceId;
+use anyhow::{Result, anyhow};
+use cargo_metadata::{
+    CrateType, Node, Package, Target,
+    camino::{Utf8Component, Utf8Path, Utf8PathBuf},
+};
 
 use crate::{
     buck::{
-        BuildscriptRun, CargoManifest, CargoTargetKind, FileGroup, GitFetch, Glob, HttpArchive,
-        RustBinary, RustLibrary, RustRule, RustTest,
+        Alias, BuildscriptRun, CargoManifest, CargoTargetKind, FileGroup, GitFetch, Glob,
+        HttpArchive, RustBinary, RustLibrary, RustRule, RustTest,
     },
     context::BuckalContext,
+    crate_index::download_urls,
     platform::{buck_labels, lookup_platforms},
-    utils::{UnwrapOrExit, get_cfgs, get_target, get_vendor_path_relative},
+    utils::{
+        UnwrapOrExit, get_buck2_root, get_cfgs, get_git_repo_path_relative, get_target,
+        get_vendor_path_relative, parse_git_source,
+    },
 };
 
-use super::deps::{dep_kind_matches, set_deps};
+use super::actions::is_third_party;
+use super::deps::{artifact_env, dep_kind_matches, set_deps};
+use super::features::{common_features, exec_features};
 
 /// Emit `rust_library` rule for the given lib target
 pub(super) fn emit_rust_library(
@@ -24,37 +36,83 @@ pub(super) fn emit_rust_library(
     buckal_name: &str,
     ctx: &BuckalContext,
 ) -> RustLibrary {
+    build_rust_library(
+        package,
+        node,
+        lib_target,
+        manifest_dir,
+        buckal_name,
+        None,
+        ctx,
+    )
+}
+
+/// Emit the exec variant of the `rust_library` rule for the given lib target, if it needs one.
+///
+/// Build scripts, proc-macros and their dependencies are built for the exec platform, where Cargo
+/// resolves the features of a package independently of the target.
+pub(super) fn emit_exec_rust_library(
+    package: &Package,
+    node: &Node,
+    lib_target: &Target,
+    manifest_dir: &Utf8PathBuf,
+    buckal_name: &str,
+    ctx: &BuckalContext,
+) -> Option<RustLibrary> {
+    let features = exec_features(node, ctx)?.clone();
+    Some(build_rust_library(
+        package,
+        node,
+        lib_target,
+        manifest_dir,
+        &get_exec_name(buckal_name),
+        Some(features),
+        ctx,
+    ))
+}
+
+/// Get the name of the exec variant of a rule
+pub(super) fn get_exec_name(buckal_name: &str) -> String {
+    format!("{}-exec", buckal_name)
+}
+
+/// Build a `rust_library` rule, for the exec platform when `exec_features` is given
+fn build_rust_library(
+    package: &Package,
+    node: &Node,
+    lib_target: &Target,
+    manifest_dir: &Utf8PathBuf,
+    buckal_name: &str,
+    exec_features: Option<Set<String>>,
+    ctx: &BuckalContext,
+) -> RustLibrary {
+    let exec = exec_features.is_some();
     let mut rust_library = RustLibrary {
         name: buckal_name.to_owned(),
         srcs: Set::from([get_vendor_target()]),
         crate_name: lib_target.name.to_owned().replace("-", "_"),
         edition: package.edition.to_string(),
-        features: Set::from_iter(node.features.iter().map(|f| f.to_string())),
-        rustc_flags: Set::from(["@$(location :manifest[env_flags])".to_owned()]),
+        features: exec_features.unwrap_or_else(|| common_features(node, ctx)),
+        rustc_flags: rustc_flags(package, ctx),
         visibility: Set::from(["PUBLIC".to_owned()]),
         ..Default::default()
     };
 
-    if lib_target
+    let proc_macro = lib_target
         .kind
-        .contains(&cargo_metadata::TargetKind::ProcMacro)
-    {
+        .contains(&cargo_metadata::TargetKind::ProcMacro);
+    if proc_macro {
         rust_library.proc_macro = Some(true);
     }
 
+    if let Some(crate_type) = primary_crate_type(lib_target) {
+        set_crate_type(&mut rust_library, crate_type);
+    }
+
     // Set the crate root path
-    rust_library.crate_root = format!(
-        "{}/{}",
-        get_vendor_name(),
-        normalize_path_for_buck(
-            lib_target
-                .src_path
-                .to_owned()
-                .strip_prefix(manifest_dir)
-                .expect("Failed to get library source path")
-                .as_str()
-        )
-    );
+    (rust_library.crate_root, rust_library.mapped_srcs) =
+        resolve_crate_root(lib_target, manifest_dir, ctx)
+            .unwrap_or_exit_ctx(format!("failed to set crate root for '{}'", buckal_name));
 
     // look up platform compatibility
     if let Some(platforms) = lookup_platforms(&package.name) {
@@ -62,12 +120,81 @@ pub(super) fn emit_rust_library(
     }
 
     // Set dependencies
-    set_deps(&mut rust_library, node, CargoTargetKind::Lib, ctx)
-        .unwrap_or_exit_ctx(format!("failed to set dependencies for '{}'", buckal_name));
+    // Proc-macros run in the compiler, so their dependencies are built for the exec platform
+    set_deps(
+        &mut rust_library,
+        node,
+        target_deps_kind(lib_target, CargoTargetKind::Lib),
+        exec || proc_macro,
+        ctx,
+    )
+    .unwrap_or_exit_ctx(format!("failed to set dependencies for '{}'", buckal_name));
 
     rust_library
 }
 
+/// Get the base `rustc` flags of the rules of the package, with the lint policy of `buckal.toml`
+fn rustc_flags(package: &Package, ctx: &BuckalContext) -> Set<String> {
+    let mut rustc_flags = Set::from(["@$(location :manifest[env_flags])".to_owned()]);
+    // Registry packages may be vendored under the workspace root, so only the source tells
+    let policy_flag = if is_third_party(package) {
+        ctx.repo_config.cap_lints.rustc_flag()
+    } else {
+        ctx.repo_config.warnings.rustc_flag()
+    };
+    rustc_flags.extend(policy_flag);
+    rustc_flags
+}
+
+/// Get the crate type of the main `rust_library` rule of the given lib target, if it is not an rlib
+///
+/// Rust dependents can only link `lib`, `rlib` and `proc-macro` crates, so these take precedence.
+pub(super) fn primary_crate_type(lib_target: &Target) -> Option<&CrateType> {
+    if lib_target.crate_types.iter().any(|c| {
+        matches!(
+            c,
+            CrateType::Lib | CrateType::RLib | CrateType::ProcMacro | CrateType::Bin
+        )
+    }) {
+        return None;
+    }
+    lib_target.crate_types.first()
+}
+
+/// Get the crate types of the given lib target that need a `rust_library` rule of their own
+pub(super) fn extra_crate_types(lib_target: &Target) -> Vec<&CrateType> {
+    let primary = primary_crate_type(lib_target);
+    lib_target
+        .crate_types
+        .iter()
+        .filter(|c| {
+            matches!(
+                c,
+                CrateType::CDyLib | CrateType::DyLib | CrateType::StaticLib
+            ) && Some(*c) != primary
+        })
+        .collect()
+}
+
+/// Get the name of the extra `rust_library` rule building the given crate type
+pub(super) fn get_crate_type_name(buckal_name: &str, crate_type: &CrateType) -> String {
+    format!("{}-{}", buckal_name, crate_type)
+}
+
+/// Set the crate type and linkage attributes of a `rust_library` rule
+pub(super) fn set_crate_type(rust_library: &mut RustLibrary, crate_type: &CrateType) {
+    let (preferred_linkage, link_style) = match crate_type {
+        // A cdylib bundles all of its Rust dependencies as position independent code
+        CrateType::CDyLib => ("shared", "static_pic"),
+        CrateType::DyLib => ("shared", "shared"),
+        CrateType::StaticLib => ("static", "static"),
+        _ => return,
+    };
+    rust_library.crate_type = Some(crate_type.to_string());
+    rust_library.preferred_linkage = Some(preferred_linkage.to_owned());
+    rust_library.link_style = Some(link_style.to_owned());
+}
+
 /// Emit `rust_binary` rule for the given bin target
 pub(super) fn emit_rust_binary(
     package: &Package,
@@ -82,29 +209,26 @@ pub(super) fn emit_rust_binary(
         srcs: Set::from([get_vendor_target()]),
         crate_name: bin_target.name.to_owned().replace("-", "_"),
         edition: package.edition.to_string(),
-        features: Set::from_iter(node.features.iter().map(|f| f.to_string())),
-        rustc_flags: Set::from(["@$(location :manifest[env_flags])".to_owned()]),
+        features: common_features(node, ctx),
+        rustc_flags: rustc_flags(package, ctx),
         visibility: Set::from(["PUBLIC".to_owned()]),
         ..Default::default()
     };
 
     // Set the crate root path
-    rust_binary.crate_root = format!(
-        "{}/{}",
-        get_vendor_name(),
-        normalize_path_for_buck(
-            bin_target
-                .src_path
-                .to_owned()
-                .strip_prefix(manifest_dir)
-                .expect("Failed to get binary source path")
-                .as_str()
-        )
-    );
+    (rust_binary.crate_root, rust_binary.mapped_srcs) =
+        resolve_crate_root(bin_target, manifest_dir, ctx)
+            .unwrap_or_exit_ctx(format!("failed to set crate root for '{}'", buckal_name));
 
     // Set dependencies
-    set_deps(&mut rust_binary, node, CargoTargetKind::Bin, ctx)
-        .unwrap_or_exit_ctx(format!("failed to set dependencies for '{}'", buckal_name));
+    set_deps(
+        &mut rust_binary,
+        node,
+        target_deps_kind(bin_target, CargoTargetKind::Bin),
+        false,
+        ctx,
+    )
+    .unwrap_or_exit_ctx(format!("failed to set dependencies for '{}'", buckal_name));
 
     if let Some(platforms) = lookup_platforms(&package.name) {
         rust_binary.compatible_with = buck_labels(&platforms);
@@ -127,28 +251,19 @@ pub(super) fn emit_rust_test(
         srcs: Set::from([get_vendor_target()]),
         crate_name: test_target.name.to_owned().replace("-", "_"),
         edition: package.edition.to_string(),
-        features: Set::from_iter(node.features.iter().map(|f| f.to_string())),
-        rustc_flags: Set::from(["@$(location :manifest[env_flags])".to_owned()]),
+        features: common_features(node, ctx),
+        rustc_flags: rustc_flags(package, ctx),
         visibility: Set::from(["PUBLIC".to_owned()]),
         ..Default::default()
     };
 
     // Set the crate root path
-    rust_test.crate_root = format!(
-        "{}/{}",
-        get_vendor_name(),
-        normalize_path_for_buck(
-            test_target
-                .src_path
-                .to_owned()
-                .strip_prefix(manifest_dir)
-                .expect("Failed to get test source path")
-                .as_str()
-        )
-    );
+    (rust_test.crate_root, rust_test.mapped_srcs) =
+        resolve_crate_root(test_target, manifest_dir, ctx)
+            .unwrap_or_exit_ctx(format!("failed to set crate root for '{}'", buckal_name));
 
     // Set dependencies
-    set_deps(&mut rust_test, node, CargoTargetKind::Test, ctx)
+    set_deps(&mut rust_test, node, CargoTargetKind::Test, false, ctx)
         .unwrap_or_exit_ctx(format!("failed to set dependencies for '{}'", buckal_name));
 
     if let Some(platforms) = lookup_platforms(&package.name) {
@@ -172,30 +287,24 @@ pub(super) fn emit_buildscript_build(
         srcs: Set::from([get_vendor_target()]),
         crate_name: build_target.name.to_owned().replace("-", "_"),
         edition: package.edition.to_string(),
-        features: Set::from_iter(node.features.iter().map(|f| f.to_string())),
-        rustc_flags: Set::from(["@$(location :manifest[env_flags])".to_owned()]),
+        features: common_features(node, ctx),
+        rustc_flags: rustc_flags(package, ctx),
         ..Default::default()
     };
 
     // Set the crate root path for the build script
-    buildscript_build.crate_root = format!(
-        "{}/{}",
-        get_vendor_name(),
-        normalize_path_for_buck(
-            build_target
-                .src_path
-                .to_owned()
-                .strip_prefix(manifest_dir)
-                .expect("Failed to get build script source path")
-                .as_str()
-        )
-    );
+    (buildscript_build.crate_root, buildscript_build.mapped_srcs) =
+        resolve_crate_root(build_target, manifest_dir, ctx).unwrap_or_exit_ctx(format!(
+            "failed to set crate root for '{}'",
+            build_target.name
+        ));
 
     // Set dependencies for the build script
     set_deps(
         &mut buildscript_build,
         node,
         CargoTargetKind::CustomBuild,
+        true,
         ctx,
     )
     .unwrap_or_exit_ctx(format!(
@@ -214,13 +323,12 @@ pub(super) fn emit_buildscript_run(
     ctx: &BuckalContext,
 ) -> BuildscriptRun {
     // create the build script run rule
-    let build_name = get_build_name(&build_target.name);
     let mut buildscript_run = BuildscriptRun {
-        name: format!("{}-run", build_name),
+        name: get_buildscript_run_name(build_target),
         package_name: package.name.to_string(),
         buildscript_rule: format!(":{}", build_target.name),
         env_srcs: Set::from([":manifest[env_dict]".to_owned()]),
-        features: Set::from_iter(node.features.iter().map(|f| f.to_string())),
+        features: common_features(node, ctx),
         version: package.version.to_string(),
         manifest_dir: get_vendor_target(),
         visibility: Set::from(["PUBLIC".to_owned()]),
@@ -264,36 +372,48 @@ pub(super) fn emit_buildscript_run(
         }
     }
 
+    // Build scripts locate the binaries of their artifact dependencies when they run
+    buildscript_run.env.extend(
+        artifact_env(node, CargoTargetKind::CustomBuild, ctx).unwrap_or_exit_ctx(format!(
+            "failed to set artifact dependencies for '{}'",
+            buildscript_run.name
+        )),
+    );
+
     buildscript_run
 }
 
 /// Patch the given `rust_library` or `rust_binary` rule to support build scripts
-pub(super) fn patch_with_buildscript(rust_rule: &mut dyn RustRule, build_target: &Target) {
-    let build_name = get_build_name(&build_target.name);
+pub(super) fn patch_with_buildscript(rust_rule: &mut dyn RustRule, run_name: &str) {
     rust_rule.env_mut().insert(
         "OUT_DIR".to_owned(),
-        format!("$(location :{build_name}-run[out_dir])").to_owned(),
+        format!("$(location :{run_name}[out_dir])").to_owned(),
     );
     rust_rule
         .rustc_flags_mut()
-        .insert(format!("@$(location :{build_name}-run[rustc_flags])",).to_owned());
+        .insert(format!("@$(location :{run_name}[rustc_flags])",).to_owned());
+}
+
+/// Get the name of the `buildscript_run` rule of the given build target
+pub(super) fn get_buildscript_run_name(build_target: &Target) -> String {
+    format!("{}-run", get_build_name(&build_target.name))
 }
 
 /// Emit `http_archive` rule for the given package
 pub(super) fn emit_http_archive(package: &Package, ctx: &BuckalContext) -> HttpArchive {
-    let url = format!(
-        "https://static.crates.io/crates/{}/{}-{}.crate",
-        package.name, package.name, package.version
-    );
     let buckal_name = format!("{}-{}", package.name, package.version);
     let checksum = ctx
         .checksums_map
         .get(&format!("{}-{}", package.name, package.version))
         .unwrap();
+    let urls =
+        download_urls(package, checksum, &ctx.cargo_config, &ctx.repo_config).unwrap_or_exit_ctx(
+            format!("failed to get the download URL of `{}`", buckal_name),
+        );
 
     HttpArchive {
         name: get_vendor_name().to_string(),
-        urls: Set::from([url]),
+        urls,
         sha256: checksum.to_string(),
         _type: "tar.gz".to_owned(),
         strip_prefix: buckal_name,
@@ -310,32 +430,79 @@ pub(super) fn emit_filegroup() -> FileGroup {
             ..Default::default()
         },
         out: None,
+        visibility: Set::new(),
     }
 }
 
-/// Emit `git_fetch` rule for the given package
-pub(super) fn emit_git_fetch(package: &Package) -> GitFetch {
-    let source_id = TomlLockfileSourceId::new(
-        package
-            .source
-            .as_ref()
-            .expect("failed to get package source")
-            .repr
-            .to_owned(),
-    )
-    .unwrap_or_exit();
-
-    let mut git_repo = source_id.url().to_owned();
-    git_repo.set_fragment(None);
-    git_repo.set_query(None);
+/// Emit the `git_fetch` rule shared by the crates of the git source of the given package
+///
+/// Every package of the same repository and revision gets a subtarget for its directory.
+pub(super) fn emit_git_fetch(package: &Package, ctx: &BuckalContext) -> GitFetch {
+    let source = &package
+        .source
+        .as_ref()
+        .expect("failed to get package source")
+        .repr;
+    let (repo, rev) = parse_git_source(source).unwrap_or_exit();
+
+    let sub_targets = ctx
+        .packages_map
+        .values()
+        .filter(|p| p.source.as_ref().is_some_and(|s| &s.repr == source))
+        .filter_map(git_sub_dir)
+        .collect();
 
     GitFetch {
         name: get_vendor_name().to_string(),
-        repo: git_repo.to_string(),
-        rev: source_id.url().fragment().unwrap().to_string(),
+        repo,
+        rev,
+        shallow: ctx.repo_config.git.shallow,
+        submodules: ctx.repo_config.git.submodules,
+        sub_targets,
+        visibility: Set::from(["PUBLIC".to_owned()]),
+    }
+}
+
+/// Emit the `alias` rule of the given git package to the `git_fetch` rule of its repository
+pub(super) fn emit_git_alias(package: &Package) -> Alias {
+    let source = &package
+        .source
+        .as_ref()
+        .expect("failed to get package source")
+        .repr;
+    Alias {
+        name: get_vendor_name().to_string(),
+        actual: format!(
+            "//{}:{}",
+            get_git_repo_path_relative(source).unwrap_or_exit(),
+            get_vendor_name()
+        ),
     }
 }
 
+/// Get the root of the Cargo git checkout containing the given directory.
+///
+/// Cargo checks out git sources to `$CARGO_HOME/git/checkouts/<repo>-<hash>/<short rev>`.
+pub(super) fn git_checkout_root(dir: &Utf8Path) -> Option<&Utf8Path> {
+    dir.ancestors().find(|ancestor| {
+        let checkouts = ancestor.parent().and_then(Utf8Path::parent);
+        checkouts.and_then(Utf8Path::file_name) == Some("checkouts")
+            && checkouts
+                .and_then(Utf8Path::parent)
+                .and_then(Utf8Path::file_name)
+                == Some("git")
+    })
+}
+
+/// Get the directory of a git package inside its repository, if it is not at the root.
+pub(super) fn git_sub_dir(package: &Package) -> Option<String> {
+    let manifest_dir = package.manifest_path.parent()?;
+    let sub_dir = manifest_dir
+        .strip_prefix(git_checkout_root(manifest_dir)?)
+        .ok()?;
+    (!sub_dir.as_str().is_empty()).then(|| normalize_path_for_buck(sub_dir.as_str()))
+}
+
 /// Emit `cargo_manifest` rule for the given package
 pub(super) fn emit_cargo_manifest() -> CargoManifest {
     CargoManifest {
@@ -344,6 +511,19 @@ pub(super) fn emit_cargo_manifest() -> CargoManifest {
     }
 }
 
+/// Get the kind used to resolve dependencies of the given target
+///
+/// Example and bench targets reuse the lib/bin emitters but resolve dev-dependencies like Cargo does.
+fn target_deps_kind(target: &Target, default: CargoTargetKind) -> CargoTargetKind {
+    if target.kind.contains(&cargo_metadata::TargetKind::Example) {
+        CargoTargetKind::Example
+    } else if target.kind.contains(&cargo_metadata::TargetKind::Bench) {
+        CargoTargetKind::Bench
+    } else {
+        default
+    }
+}
+
 fn get_build_name(s: &str) -> Cow<'_, str> {
     if let Some(stripped) = s.strip_suffix("-build") {
         Cow::Owned(stripped.to_string())
@@ -358,13 +538,234 @@ fn get_vendor_name() -> Cow<'static, str> {
 }
 
 /// Get the label of the vendor target
-fn get_vendor_target() -> String {
+pub(super) fn get_vendor_target() -> String {
     format!(":{}", get_vendor_name())
 }
 
+/// Get the crate root of the given target and the `mapped_srcs` of the rule building it.
+///
+/// Buck2 rules can only reference files inside their own package, so the sources of a target
+/// living in another workspace package (e.g. `path = "../shared/lib.rs"`) are taken from the
+/// `vendor` filegroup of that package, mapped next to the sources of the rule.
+fn resolve_crate_root(
+    target: &Target,
+    manifest_dir: &Utf8Path,
+    ctx: &BuckalContext,
+) -> Result<(String, Map<String, String>)> {
+    if let Ok(crate_root) = get_crate_root(target, manifest_dir) {
+        return Ok((crate_root, Map::new()));
+    }
+    let Some(owner) = source_owner(target, ctx) else {
+        return get_crate_root(target, manifest_dir).map(|crate_root| (crate_root, Map::new()));
+    };
+
+    let owner_dir = owner.manifest_path.parent().unwrap();
+    let buck2_root = get_buck2_root()?;
+    let package_path = owner_dir.strip_prefix(&buck2_root).map_err(|_| {
+        anyhow!(
+            "package directory `{}` is not under Buck2 root `{}`",
+            owner_dir,
+            buck2_root
+        )
+    })?;
+    let mapped_dir = format!("{}-{}", get_vendor_name(), owner.name);
+    let relative = normalize_lexically(&target.src_path)
+        .strip_prefix(normalize_lexically(owner_dir))?
+        .to_owned();
+    Ok((
+        format!(
+            "{}/{}",
+            mapped_dir,
+            normalize_path_for_buck(relative.as_str())
+        ),
+        Map::from([(
+            format!(
+                "//{}:{}",
+                normalize_path_for_buck(package_path.as_str()),
+                get_vendor_name()
+            ),
+            mapped_dir,
+        )]),
+    ))
+}
+
+/// Get the workspace package owning the sources of the given target, the innermost one like
+/// Buck2 packages.
+fn source_owner<'a>(target: &Target, ctx: &'a BuckalContext) -> Option<&'a Package> {
+    let src_path = normalize_lexically(&target.src_path);
+    ctx.packages_map
+        .values()
+        .filter(|package| !is_third_party(package))
+        .filter(|package| {
+            src_path.starts_with(
+                normalize_lexically(&package.manifest_path)
+                    .parent()
+                    .unwrap(),
+            )
+        })
+        .max_by_key(|package| package.manifest_path.as_str().len())
+}
+
+/// Whether targets of other workspace packages are built from sources of the given package, which
+/// then has to make its `vendor` filegroup visible to them.
+pub(super) fn exports_sources(package: &Package, ctx: &BuckalContext) -> bool {
+    ctx.packages_map
+        .values()
+        .filter(|other| other.id != package.id)
+        .flat_map(|other| {
+            let other_dir = other.manifest_path.parent().unwrap();
+            other
+                .targets
+                .iter()
+                .filter(move |t| get_crate_root(t, other_dir).is_err())
+                .map(move |t| (other, t))
+        })
+        .filter(|(other, _)| !is_third_party(other))
+        .any(|(_, t)| source_owner(t, ctx).is_some_and(|owner| owner.id == package.id))
+}
+
+/// Get the crate root of the given target, relative to the vendor filegroup of its package
+fn get_crate_root(target: &Target, manifest_dir: &Utf8Path) -> Result<String> {
+    let src_path = normalize_lexically(&target.src_path);
+    let relative = src_path
+        .strip_prefix(normalize_lexically(manifest_dir))
+        .map_err(|_| {
+            anyhow!(
+                "source file `{}` of target `{}` is outside of the package directory `{}` and of \
+                 any other workspace package.\n\
+                 Buck2 rules cannot reference files outside of their package, please move the \
+                 sources into a package or extract them into a separate crate.",
+                target.src_path,
+                target.name,
+                manifest_dir
+            )
+        })?;
+    Ok(format!(
+        "{}/{}",
+        get_vendor_name(),
+        normalize_path_for_buck(relative.as_str())
+    ))
+}
+
+/// Resolve `.` and `..` components of a path without touching the filesystem
+fn normalize_lexically(path: &Utf8Path) -> Utf8PathBuf {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
 /// Normalize a path for Buck by converting backslashes to forward slashes.
 /// This normalization is critical on Windows, where paths use backslashes,
 /// as Buck2 requires forward slashes in all generated BUCK files regardless of the host platform.
 fn normalize_path_for_buck(path: &str) -> String {
     path.replace('\\', "/")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_target(name: &str, src_path: &str) -> Target {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "kind": ["lib"],
+            "crate_types": [],
+            "required_features": [],
+            "src_path": src_path,
+            "edition": "2021",
+            "doctest": true,
+            "test": true
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_get_crate_root() {
+        let manifest_dir = Utf8PathBuf::from("/ws/foo");
+
+        let target = mock_target("foo", "/ws/foo/src/lib.rs");
+        assert_eq!(
+            get_crate_root(&target, &manifest_dir).unwrap(),
+            "vendor/src/lib.rs"
+        );
+
+        // `..` components that stay inside the package are resolved
+        let target = mock_target("foo", "/ws/foo/src/../lib/mod.rs");
+        assert_eq!(
+            get_crate_root(&target, &manifest_dir).unwrap(),
+            "vendor/lib/mod.rs"
+        );
+
+        // Sources outside of the package are reported instead of panicking
+        let target = mock_target("shared", "/ws/foo/../shared/src/lib.rs");
+        let err = get_crate_root(&target, &manifest_dir).unwrap_err();
+        assert!(err.to_string().contains("outside of the package directory"));
+    }
+
+    #[test]
+    fn test_rustc_flags_vendored_registry_package() {
+        let ctx = BuckalContext {
+            repo_config: crate::config::RepoConfig {
+                warnings: crate::config::WarningsPolicy::Deny,
+                ..Default::default()
+            },
+            ..crate::context::mock_context()
+        };
+        // A registry package vendored under the workspace root keeps its lints capped
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "serde",
+            "version": "1.0.0",
+            "id": "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0",
+            "source": "registry+https://github.com/rust-lang/crates.io-index",
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": format!("{}/vendor/serde-1.0.0/Cargo.toml", ctx.workspace_root),
+            "edition": "2018"
+        }))
+        .unwrap();
+        assert_eq!(
+            rustc_flags(&package, &ctx),
+            Set::from([
+                "--cap-lints=allow".to_owned(),
+                "@$(location :manifest[env_flags])".to_owned(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_git_checkout_root() {
+        let manifest_dir =
+            Utf8PathBuf::from("/home/user/.cargo/git/checkouts/tokio-a1b2c3/d4e5f6a/tokio-util");
+        let checkout_root = git_checkout_root(&manifest_dir).unwrap();
+        assert_eq!(
+            checkout_root,
+            "/home/user/.cargo/git/checkouts/tokio-a1b2c3/d4e5f6a"
+        );
+
+        // Crate roots of packages in a subdirectory are prefixed with it
+        let target = mock_target(
+            "tokio_util",
+            "/home/user/.cargo/git/checkouts/tokio-a1b2c3/d4e5f6a/tokio-util/src/lib.rs",
+        );
+        assert_eq!(
+            get_crate_root(&target, checkout_root).unwrap(),
+            "vendor/tokio-util/src/lib.rs"
+        );
+
+        assert_eq!(
+            git_checkout_root(Utf8Path::new(
+                "/home/user/.cargo/registry/src/index.crates.io-1949cf8c6b5b557f/serde-1.0.0"
+            )),
+            None
+        );
+    }
+}
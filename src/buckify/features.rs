@@ -0,0 +1,929 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+
+use cargo_metadata::{Node, Package, Target, TargetKind};
+
+use crate::{
+    FEATURES_CONFIG_SECTION,
+    buck::{ConfigSetting, Rule},
+    buckal_warn,
+    context::BuckalContext,
+    platform::target_setting,
+};
+
+use super::{
+    emit::get_exec_name,
+    rules::{get_bench_name, get_bin_test_name, get_example_name},
+    select::{append_select_to_attr, build_select, build_string_list, pretty_print_expr},
+};
+
+/// Rules whose `features` follow the resolved features of their package
+const FEATURE_RULE_KINDS: &[&str] = &[
+    "rust_library",
+    "rust_binary",
+    "rust_test",
+    "buildscript_run",
+];
+
+/// Get the features enabled for the package on every supported platform.
+///
+/// Falls back to the host resolve when no per-platform resolve is available.
+pub(super) fn common_features(node: &Node, ctx: &BuckalContext) -> Set<String> {
+    let features = match ctx.platform_features.get(&node.id) {
+        Some(per_target) if !per_target.is_empty() => per_target.values().skip(1).fold(
+            per_target.values().next().unwrap().clone(),
+            |common, features| common.intersection(features).cloned().collect(),
+        ),
+        _ => node.features.iter().map(|f| f.to_string()).collect(),
+    };
+    without_exec_only_features(node, ctx, features)
+}
+
+/// Get the features of the exec variant of the package, if it needs one.
+pub(super) fn exec_features<'a>(node: &Node, ctx: &'a BuckalContext) -> Option<&'a Set<String>> {
+    ctx.exec_features.get(&node.id).map(|f| &f.exec)
+}
+
+/// Drop the features only enabled for the exec platform, which `cargo metadata` reports as well.
+fn without_exec_only_features(
+    node: &Node,
+    ctx: &BuckalContext,
+    features: Set<String>,
+) -> Set<String> {
+    match ctx.exec_features.get(&node.id) {
+        Some(split) => features.intersection(&split.target).cloned().collect(),
+        None => features,
+    }
+}
+
+/// Features only enabled on some platforms
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct PlatformFeatures {
+    /// Features of the target triples whose features differ from the default ones
+    targets: Map<String, Set<String>>,
+    /// Features of the other platforms, the ones of the host resolve
+    default: Set<String>,
+}
+
+/// Get the features only enabled on some platforms.
+///
+/// Platforms without a per-platform resolve, such as the ones passed with `--target-platforms`,
+/// keep the features of the host resolve like before features were resolved per platform.
+pub(super) fn platform_only_features(node: &Node, ctx: &BuckalContext) -> PlatformFeatures {
+    let Some(per_target) = ctx.platform_features.get(&node.id) else {
+        return PlatformFeatures::default();
+    };
+    let common = common_features(node, ctx);
+    let extra = |features: Set<String>| -> Set<String> {
+        without_exec_only_features(node, ctx, features)
+            .difference(&common)
+            .cloned()
+            .collect()
+    };
+    let default = extra(node.features.iter().map(|f| f.to_string()).collect());
+    let targets = per_target
+        .iter()
+        .map(|(triple, features)| (triple.clone(), extra(features.clone())))
+        .filter(|(_, features)| *features != default)
+        .collect();
+    PlatformFeatures { targets, default }
+}
+
+/// Render a `select()` adding the platform-only features on their target.
+fn render_platform_features_select(platform_features: &PlatformFeatures) -> String {
+    let settings: Vec<String> = platform_features
+        .targets
+        .keys()
+        .map(|t| target_setting(t))
+        .collect();
+    let default: Vec<String> = platform_features.default.iter().cloned().collect();
+    let lists: Vec<(&str, _)> = settings
+        .iter()
+        .zip(platform_features.targets.values())
+        .map(|(setting, features)| {
+            let features: Vec<String> = features.iter().cloned().collect();
+            (setting.as_str(), build_string_list(&features))
+        })
+        .chain(std::iter::once(("DEFAULT", build_string_list(&default))))
+        .collect();
+
+    let mut out = String::new();
+    pretty_print_expr(&build_select(&lists), &mut out, 4);
+    out
+}
+
+/// Patch the `features` of the rules in the BUCK content of the package with its platform-only
+/// features.
+///
+/// The exec variant of the library keeps the features resolved for the exec platform.
+pub(super) fn patch_platform_features(
+    mut buck_content: String,
+    buck_rules: &[Rule],
+    node: &Node,
+    ctx: &BuckalContext,
+) -> String {
+    let platform_features = platform_only_features(node, ctx);
+    if platform_features.targets.is_empty() {
+        return buck_content;
+    }
+
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let exec_library = get_exec_name(&package.name);
+    let select_expr = render_platform_features_select(&platform_features);
+    for rule in buck_rules {
+        let (kind, name) = match rule {
+            Rule::RustLibrary(r) if r.name == exec_library => continue,
+            Rule::RustLibrary(r) => ("rust_library", &r.name),
+            Rule::RustBinary(r) => ("rust_binary", &r.name),
+            Rule::RustTest(r) => ("rust_test", &r.name),
+            Rule::BuildscriptRun(r) => ("buildscript_run", &r.name),
+            _ => continue,
+        };
+        buck_content =
+            append_select_to_attr(&buck_content, kind, Some(name), "features", &select_expr);
+    }
+    buck_content
+}
+
+/// Name of the `config_setting` disabling the `default` feature of a first-party package
+const NO_DEFAULT_FEATURES_SETTING: &str = "no-default-features";
+
+/// Name of the `config_setting` enabling the given feature of a first-party package
+fn feature_setting_name(feature: &str) -> String {
+    format!("feature-{}", feature)
+}
+
+/// Features and dependencies enabled by a feature of a package
+struct ExpandedFeature {
+    features: Set<String>,
+    deps: Set<String>,
+    /// Features of dependencies, as `(dependency, feature)`
+    dep_features: Set<(String, String)>,
+}
+
+/// Expand a feature of the package into the features and the dependencies it enables.
+fn expand_feature(package: &Package, feature: &str) -> ExpandedFeature {
+    let mut expanded = ExpandedFeature {
+        features: Set::new(),
+        deps: Set::new(),
+        dep_features: Set::new(),
+    };
+    let mut pending = vec![feature.to_owned()];
+    while let Some(feature) = pending.pop() {
+        if !expanded.features.insert(feature.clone()) {
+            continue;
+        }
+        for value in package.features.get(&feature).into_iter().flatten() {
+            if let Some(dep) = value.strip_prefix("dep:") {
+                expanded.deps.insert(dep.to_owned());
+            } else if let Some((dep, dep_feature)) = value.split_once('/') {
+                // `dep?/feature` only enables the feature of an already enabled dependency
+                let (dep, weak) = match dep.strip_suffix('?') {
+                    Some(dep) => (dep, true),
+                    None => (dep, false),
+                };
+                expanded
+                    .dep_features
+                    .insert((dep.to_owned(), dep_feature.to_owned()));
+                if !weak {
+                    expanded.deps.insert(dep.to_owned());
+                    if package.features.contains_key(dep) {
+                        pending.push(dep.to_owned());
+                    }
+                }
+            } else {
+                pending.push(value.to_owned());
+            }
+        }
+    }
+    expanded
+}
+
+/// Whether any of the dependencies is an optional dependency left out of the resolve.
+fn has_unresolved_deps(package: &Package, node: &Node, deps: &Set<String>) -> bool {
+    let normalize = |name: &str| name.replace('-', "_");
+    package.dependencies.iter().any(|dep| {
+        let name = dep.rename.as_deref().unwrap_or(&dep.name);
+        dep.optional
+            && deps.contains(name)
+            && !node
+                .deps
+                .iter()
+                .any(|resolved| normalize(&resolved.name) == normalize(name))
+    })
+}
+
+/// Get the features of resolved dependencies that the BUCK files do not enable, as `dep/feature`.
+///
+/// Dependencies are built with the features resolved at migration time, so code needing more of
+/// them would not compile.
+fn unresolved_dep_features(
+    node: &Node,
+    ctx: &BuckalContext,
+    dep_features: &Set<(String, String)>,
+) -> Vec<String> {
+    let normalize = |name: &str| name.replace('-', "_");
+    dep_features
+        .iter()
+        .filter(|(dep, feature)| {
+            node.deps
+                .iter()
+                .filter(|resolved| normalize(&resolved.name) == normalize(dep))
+                .filter_map(|resolved| ctx.nodes_map.get(&resolved.pkg))
+                .any(|dep_node| !common_features(dep_node, ctx).contains(feature))
+        })
+        .map(|(dep, feature)| format!("{}/{}", dep, feature))
+        .collect()
+}
+
+/// Get the features of the package only enabled through its `default` feature.
+fn default_only_features(package: &Package, enabled: &Set<String>) -> Set<String> {
+    if !enabled.contains("default") {
+        return Set::new();
+    }
+    expand_feature(package, "default")
+        .features
+        .intersection(enabled)
+        .cloned()
+        .collect()
+}
+
+/// Get the features which can be enabled when building the package, with the features each one adds.
+///
+/// Features pulling in dependencies that were not resolved at migration time are left out, as
+/// the BUCK file has no rule for them, and so are the ones enabling features of dependencies
+/// that were not resolved.
+fn selectable_features(
+    package: &Package,
+    node: &Node,
+    ctx: &BuckalContext,
+    enabled: &Set<String>,
+) -> Map<String, Set<String>> {
+    package
+        .features
+        .keys()
+        .filter(|feature| !enabled.contains(*feature) && *feature != "default")
+        .filter_map(|feature| {
+            let expanded = expand_feature(package, feature);
+            (!has_unresolved_deps(package, node, &expanded.deps)
+                && unresolved_dep_features(node, ctx, &expanded.dep_features).is_empty())
+            .then(|| {
+                let added = expanded.features.difference(enabled).cloned().collect();
+                (feature.to_owned(), added)
+            })
+        })
+        .collect()
+}
+
+/// Emit the `config_setting` rules selecting the features of a first-party package.
+pub(super) fn emit_feature_settings(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let enabled = common_features(node, ctx);
+
+    for feature in package.features.keys().filter(|f| !enabled.contains(*f)) {
+        let unresolved =
+            unresolved_dep_features(node, ctx, &expand_feature(package, feature).dep_features);
+        if !unresolved.is_empty() {
+            buckal_warn!(
+                "Feature `{}` of `{}` cannot be selected at build time, as it enables `{}` which `cargo buckal migrate` did not resolve.",
+                feature,
+                package.name,
+                unresolved.join("`, `")
+            );
+        }
+    }
+
+    let mut settings = Vec::new();
+    if !default_only_features(package, &enabled).is_empty() {
+        settings.push(ConfigSetting {
+            name: NO_DEFAULT_FEATURES_SETTING.to_owned(),
+            values: Map::from([(
+                format!("{}.{}.default", FEATURES_CONFIG_SECTION, package.name),
+                "false".to_owned(),
+            )]),
+        });
+    }
+    for feature in selectable_features(package, node, ctx, &enabled).keys() {
+        settings.push(ConfigSetting {
+            name: feature_setting_name(feature),
+            values: Map::from([(
+                format!("{}.{}.{}", FEATURES_CONFIG_SECTION, package.name, feature),
+                "true".to_owned(),
+            )]),
+        });
+    }
+    settings.into_iter().map(Rule::ConfigSetting).collect()
+}
+
+/// Remove the features only enabled through `default` from the rules of a first-party package,
+/// they are added back by [`patch_feature_selects`] unless the `default` feature is disabled.
+pub(super) fn strip_default_only_features(rules: &mut [Rule], node: &Node, ctx: &BuckalContext) {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let default_only = default_only_features(package, &common_features(node, ctx));
+    if default_only.is_empty() {
+        return;
+    }
+    for rule in rules {
+        let features = match rule {
+            Rule::RustLibrary(r) => &mut r.features,
+            Rule::RustBinary(r) => &mut r.features,
+            Rule::RustTest(r) => &mut r.features,
+            Rule::BuildscriptRun(r) => &mut r.features,
+            _ => continue,
+        };
+        features.retain(|f| !default_only.contains(f));
+    }
+}
+
+/// Render a `select()` adding the features on the given `config_setting`.
+fn render_feature_select(setting: &str, on: &Set<String>, off: &Set<String>) -> String {
+    let on: Vec<String> = on.iter().cloned().collect();
+    let off: Vec<String> = off.iter().cloned().collect();
+    let lists = [
+        (setting, build_string_list(&on)),
+        ("DEFAULT", build_string_list(&off)),
+    ];
+
+    let mut out = String::new();
+    pretty_print_expr(&build_select(&lists), &mut out, 4);
+    out
+}
+
+/// Patch the `features` of every rule in the BUCK content of a first-party package so that they
+/// follow the features selected with `-c cargo_features.<package>.<name>=true|false`.
+pub(super) fn patch_feature_selects(
+    mut buck_content: String,
+    node: &Node,
+    ctx: &BuckalContext,
+) -> String {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let enabled = common_features(node, ctx);
+
+    let mut selects = Vec::new();
+    let default_only = default_only_features(package, &enabled);
+    if !default_only.is_empty() {
+        selects.push(render_feature_select(
+            &format!(":{}", NO_DEFAULT_FEATURES_SETTING),
+            &Set::new(),
+            &default_only,
+        ));
+    }
+    for (feature, added) in selectable_features(package, node, ctx, &enabled) {
+        selects.push(render_feature_select(
+            &format!(":{}", feature_setting_name(&feature)),
+            &added,
+            &Set::new(),
+        ));
+    }
+
+    for select_expr in &selects {
+        for rule_kind in FEATURE_RULE_KINDS {
+            buck_content =
+                append_select_to_attr(&buck_content, rule_kind, None, "features", select_expr);
+        }
+    }
+    buck_content
+}
+
+/// When a feature of a first-party package is enabled at build time
+#[derive(Debug, PartialEq)]
+enum FeatureCondition {
+    Always,
+    /// Unless the `default` feature is disabled
+    WithDefault,
+    /// When one of the given features is selected
+    Selected(Vec<String>),
+    Never,
+}
+
+/// Get when each of the `required-features` of a target is enabled at build time.
+fn required_feature_conditions(
+    target: &Target,
+    node: &Node,
+    ctx: &BuckalContext,
+) -> Vec<FeatureCondition> {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let enabled = common_features(node, ctx);
+    let default_only = default_only_features(package, &enabled);
+    let selectable = selectable_features(package, node, ctx, &enabled);
+    target
+        .required_features
+        .iter()
+        .map(|feature| {
+            if default_only.contains(feature) {
+                return FeatureCondition::WithDefault;
+            }
+            if enabled.contains(feature) {
+                return FeatureCondition::Always;
+            }
+            let selecting: Vec<String> = selectable
+                .iter()
+                .filter(|(_, added)| added.contains(feature))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if selecting.is_empty() {
+                FeatureCondition::Never
+            } else {
+                FeatureCondition::Selected(selecting)
+            }
+        })
+        .collect()
+}
+
+/// Whether the `required-features` of a target are enabled at build time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RequiredFeatures {
+    /// In every configuration
+    Enabled,
+    /// Depending on the features selected with `cargo_features`
+    Selectable,
+    /// In no configuration, as some cannot be selected
+    Disabled,
+}
+
+pub(super) fn required_features(
+    target: &Target,
+    node: &Node,
+    ctx: &BuckalContext,
+) -> RequiredFeatures {
+    let conditions = required_feature_conditions(target, node, ctx);
+    if conditions.contains(&FeatureCondition::Never) {
+        RequiredFeatures::Disabled
+    } else if conditions.iter().all(|c| *c == FeatureCondition::Always) {
+        RequiredFeatures::Enabled
+    } else {
+        RequiredFeatures::Selectable
+    }
+}
+
+/// Render a `select()` making a rule incompatible unless the feature is enabled.
+fn render_required_feature_select(condition: &FeatureCondition) -> Option<String> {
+    let incompatible = || build_string_list(&["config//:none".to_owned()]);
+    let expr = match condition {
+        FeatureCondition::Always | FeatureCondition::Never => return None,
+        FeatureCondition::WithDefault => {
+            let setting = format!(":{}", NO_DEFAULT_FEATURES_SETTING);
+            build_select(&[
+                (setting.as_str(), incompatible()),
+                ("DEFAULT", build_string_list(&[])),
+            ])
+        }
+        // Any of the features enabling it will do, so the settings are tried in turn
+        FeatureCondition::Selected(features) => {
+            features
+                .iter()
+                .rev()
+                .fold(incompatible(), |fallback, feature| {
+                    let setting = format!(":{}", feature_setting_name(feature));
+                    build_select(&[
+                        (setting.as_str(), build_string_list(&[])),
+                        ("DEFAULT", fallback),
+                    ])
+                })
+        }
+    };
+
+    let mut out = String::new();
+    pretty_print_expr(&expr, &mut out, 4);
+    Some(out)
+}
+
+/// Get the names of the rules emitted for a target which can have `required-features`.
+fn required_features_rule_names(target: &Target) -> Vec<String> {
+    if target.kind.contains(&TargetKind::Example) {
+        vec![get_example_name(&target.name)]
+    } else if target.kind.contains(&TargetKind::Bench) {
+        vec![get_bench_name(&target.name)]
+    } else if target.kind.contains(&TargetKind::Bin) {
+        vec![target.name.clone(), get_bin_test_name(&target.name)]
+    } else {
+        vec![target.name.clone()]
+    }
+}
+
+/// Patch the `target_compatible_with` of the rules of a first-party package whose
+/// `required-features` depend on the features selected with `cargo_features`, so that they are
+/// only built when Cargo would build them.
+pub(super) fn patch_required_features(
+    mut buck_content: String,
+    buck_rules: &[Rule],
+    node: &Node,
+    ctx: &BuckalContext,
+) -> String {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    for target in package
+        .targets
+        .iter()
+        .filter(|t| !t.required_features.is_empty())
+    {
+        let conditions = required_feature_conditions(target, node, ctx);
+        if conditions.contains(&FeatureCondition::Never) {
+            continue;
+        }
+        let selects: Vec<String> = conditions
+            .iter()
+            .filter_map(render_required_feature_select)
+            .collect();
+        let names = required_features_rule_names(target);
+        for rule in buck_rules {
+            let (kind, name) = match rule {
+                Rule::RustLibrary(r) => ("rust_library", &r.name),
+                Rule::RustBinary(r) => ("rust_binary", &r.name),
+                Rule::RustTest(r) => ("rust_test", &r.name),
+                _ => continue,
+            };
+            if !names.contains(name) {
+                continue;
+            }
+            for select_expr in &selects {
+                buck_content = append_select_to_attr(
+                    &buck_content,
+                    kind,
+                    Some(name),
+                    "target_compatible_with",
+                    select_expr,
+                );
+            }
+        }
+    }
+    buck_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::mock_context;
+    use indoc::indoc;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_patch_platform_features() {
+        let id = "registry+https://github.com/rust-lang/crates.io-index#getrandom@0.2.15";
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "getrandom",
+            "version": "0.2.15",
+            "id": id,
+            "source": "registry+https://github.com/rust-lang/crates.io-index",
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": "/tmp/getrandom/Cargo.toml",
+            "edition": "2018"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "deps": [],
+            "dependencies": [],
+            "features": ["js", "std"]
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map: HashMap::from([(package.id.clone(), package)]),
+            platform_features: HashMap::from([(
+                node.id.clone(),
+                Map::from([
+                    (
+                        "x86_64-unknown-linux-gnu".to_owned(),
+                        Set::from(["std".to_owned()]),
+                    ),
+                    (
+                        "wasm32-unknown-unknown".to_owned(),
+                        Set::from(["js".to_owned(), "std".to_owned()]),
+                    ),
+                ]),
+            )]),
+            ..mock_context()
+        };
+
+        // Platforms other than the resolved ones, such as aarch64 Linux, keep the host features
+        assert_eq!(common_features(&node, &ctx), Set::from(["std".to_owned()]));
+        assert_eq!(
+            platform_only_features(&node, &ctx),
+            PlatformFeatures {
+                targets: Map::from([("x86_64-unknown-linux-gnu".to_owned(), Set::new())]),
+                default: Set::from(["js".to_owned()]),
+            }
+        );
+
+        let rules = vec![
+            Rule::RustLibrary(crate::buck::RustLibrary {
+                name: "getrandom".to_owned(),
+                ..Default::default()
+            }),
+            Rule::RustLibrary(crate::buck::RustLibrary {
+                name: "getrandom-exec".to_owned(),
+                ..Default::default()
+            }),
+            Rule::RustBinary(crate::buck::RustBinary {
+                name: "build-script-build".to_owned(),
+                ..Default::default()
+            }),
+        ];
+        let input = indoc! {r#"
+            rust_library(
+                name = "getrandom",
+                features = ["std"],
+            )
+
+            rust_library(
+                name = "getrandom-exec",
+                features = ["std"],
+            )
+
+            rust_binary(
+                name = "build-script-build",
+            )
+        "#};
+
+        // The exec variant is built for the exec platform with its own features
+        let expected = indoc! {r#"
+            rust_library(
+                name = "getrandom",
+                features = ["std"] + select({
+                    "//platforms:target-x86_64-unknown-linux-gnu": [],
+                    "DEFAULT": [
+                        "js",
+                    ],
+                }),
+            )
+
+            rust_library(
+                name = "getrandom-exec",
+                features = ["std"],
+            )
+
+            rust_binary(
+                name = "build-script-build",
+                features = select({
+                    "//platforms:target-x86_64-unknown-linux-gnu": [],
+                    "DEFAULT": [
+                        "js",
+                    ],
+                }),
+            )
+        "#};
+
+        assert_eq!(
+            patch_platform_features(input.to_owned(), &rules, &node, &ctx),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_patch_feature_selects() {
+        let id = "path+file:///tmp/demo#0.1.0";
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "demo",
+            "version": "0.1.0",
+            "id": id,
+            "source": null,
+            "dependencies": [{
+                "name": "serde",
+                "req": "^1",
+                "kind": null,
+                "optional": true,
+                "uses_default_features": true,
+                "features": [],
+                "target": null,
+                "rename": null,
+                "registry": null,
+                "source": "registry+https://github.com/rust-lang/crates.io-index"
+            }],
+            "targets": [],
+            "features": {
+                "default": ["std"],
+                "std": [],
+                "extra": ["std", "fast"],
+                "fast": [],
+                "serde": ["dep:serde"]
+            },
+            "manifest_path": "/tmp/demo/Cargo.toml",
+            "edition": "2021"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "deps": [],
+            "dependencies": [],
+            "features": ["default", "std"]
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map: HashMap::from([(package.id.clone(), package)]),
+            ..mock_context()
+        };
+
+        // `serde` pulls in a dependency missing from the resolve
+        let settings = emit_feature_settings(&node, &ctx);
+        assert_eq!(
+            settings,
+            vec![
+                Rule::ConfigSetting(ConfigSetting {
+                    name: "no-default-features".to_owned(),
+                    values: Map::from([(
+                        "cargo_features.demo.default".to_owned(),
+                        "false".to_owned()
+                    )]),
+                }),
+                Rule::ConfigSetting(ConfigSetting {
+                    name: "feature-extra".to_owned(),
+                    values: Map::from([(
+                        "cargo_features.demo.extra".to_owned(),
+                        "true".to_owned()
+                    )]),
+                }),
+                Rule::ConfigSetting(ConfigSetting {
+                    name: "feature-fast".to_owned(),
+                    values: Map::from([("cargo_features.demo.fast".to_owned(), "true".to_owned())]),
+                }),
+            ]
+        );
+
+        let mut rules = vec![Rule::RustLibrary(crate::buck::RustLibrary {
+            name: "demo".to_owned(),
+            features: Set::from(["default".to_owned(), "std".to_owned()]),
+            ..Default::default()
+        })];
+        strip_default_only_features(&mut rules, &node, &ctx);
+        let Rule::RustLibrary(rust_library) = &rules[0] else {
+            unreachable!()
+        };
+        assert!(rust_library.features.is_empty());
+
+        let input = indoc! {r#"
+            rust_library(
+                name = "demo",
+            )
+        "#};
+
+        let expected = indoc! {r#"
+            rust_library(
+                name = "demo",
+                features = select({
+                    ":no-default-features": [],
+                    "DEFAULT": [
+                        "default",
+                        "std",
+                    ],
+                }) + select({
+                    ":feature-extra": [
+                        "extra",
+                        "fast",
+                    ],
+                    "DEFAULT": [],
+                }) + select({
+                    ":feature-fast": [
+                        "fast",
+                    ],
+                    "DEFAULT": [],
+                }),
+            )
+        "#};
+
+        assert_eq!(
+            patch_feature_selects(input.to_owned(), &node, &ctx),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_patch_required_features() {
+        let id = "path+file:///tmp/demo#0.1.0";
+        let tokio_id = "registry+https://github.com/rust-lang/crates.io-index#tokio@1.40.0";
+        let bin = |name: &str, required: &[&str]| {
+            serde_json::json!({
+                "name": name,
+                "kind": ["bin"],
+                "crate_types": ["bin"],
+                "required-features": required,
+                "src_path": format!("/tmp/demo/src/bin/{}.rs", name),
+                "edition": "2021",
+                "doctest": false,
+                "test": false
+            })
+        };
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "demo",
+            "version": "0.1.0",
+            "id": id,
+            "source": null,
+            "dependencies": [],
+            "targets": [
+                bin("cli", &["cli"]),
+                bin("std-only", &["std"]),
+                bin("full", &["extra"]),
+            ],
+            "features": {
+                "default": ["std"],
+                "std": [],
+                "cli": [],
+                "admin": ["cli"],
+                "extra": ["tokio/full"],
+                "rt": ["tokio/rt"]
+            },
+            "manifest_path": "/tmp/demo/Cargo.toml",
+            "edition": "2021"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "deps": [{
+                "name": "tokio",
+                "pkg": tokio_id,
+                "dep_kinds": [{ "kind": null, "target": null }]
+            }],
+            "dependencies": [tokio_id],
+            "features": ["default", "std"]
+        }))
+        .unwrap();
+        let tokio_node: Node = serde_json::from_value(serde_json::json!({
+            "id": tokio_id,
+            "deps": [],
+            "dependencies": [],
+            "features": ["rt"]
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map: HashMap::from([(package.id.clone(), package.clone())]),
+            nodes_map: HashMap::from([(tokio_node.id.clone(), tokio_node)]),
+            ..mock_context()
+        };
+
+        // `extra` needs `tokio/full`, which the BUCK file of `tokio` does not enable
+        let enabled = common_features(&node, &ctx);
+        assert_eq!(
+            selectable_features(&package, &node, &ctx, &enabled)
+                .into_keys()
+                .collect::<Vec<_>>(),
+            vec!["admin", "cli", "rt"]
+        );
+
+        let status: Vec<RequiredFeatures> = package
+            .targets
+            .iter()
+            .map(|t| required_features(t, &node, &ctx))
+            .collect();
+        assert_eq!(
+            status,
+            vec![
+                RequiredFeatures::Selectable,
+                RequiredFeatures::Selectable,
+                RequiredFeatures::Disabled,
+            ]
+        );
+
+        let rules = vec![
+            Rule::RustBinary(crate::buck::RustBinary {
+                name: "cli".to_owned(),
+                ..Default::default()
+            }),
+            Rule::RustBinary(crate::buck::RustBinary {
+                name: "std-only".to_owned(),
+                ..Default::default()
+            }),
+        ];
+        let input = indoc! {r#"
+            rust_binary(
+                name = "cli",
+            )
+
+            rust_binary(
+                name = "std-only",
+            )
+        "#};
+
+        // `cli` is enabled by `-F cli` and `-F admin` alike
+        let expected = indoc! {r#"
+            rust_binary(
+                name = "cli",
+                target_compatible_with = select({
+                    ":feature-admin": [],
+                    "DEFAULT": select({
+                        ":feature-cli": [],
+                        "DEFAULT": [
+                            "config//:none",
+                        ],
+                    }),
+                }),
+            )
+
+            rust_binary(
+                name = "std-only",
+                target_compatible_with = select({
+                    ":no-default-features": [
+                        "config//:none",
+                    ],
+                    "DEFAULT": [],
+                }),
+            )
+        "#};
+
+        assert_eq!(
+            patch_required_features(input.to_owned(), &rules, &node, &ctx),
+            expected
+        );
+    }
+}
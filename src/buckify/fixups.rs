@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+
+use anyhow::{Context, Result, anyhow, bail};
+use cargo_metadata::{
+    Package,
+    camino::Utf8Path,
+    semver::{Version, VersionReq},
+};
+use serde::Deserialize;
+
+use crate::{buck::Rule, utils::get_fixups_path};
+
+/// Tweaks applied to the rules generated for a third-party crate
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Fixups {
+    /// Environment variables set when compiling the crate
+    env: Map<String, String>,
+    /// Extra `rustc` flags, one flag per entry such as `--cfg=foo`
+    rustc_flags: Vec<String>,
+    /// `--cfg` options passed to `rustc`
+    cfgs: Vec<String>,
+    /// Extra sources of the crate, such as files read by `include_str!` outside of the crate
+    extra_srcs: Vec<String>,
+    buildscript: BuildscriptFixups,
+    /// Fixups of the versions matching a requirement, applied over the others
+    version: Map<String, Fixups>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BuildscriptFixups {
+    /// Whether to run the build script at all, `true` unless set
+    run: Option<bool>,
+    /// Environment variables set when running the build script
+    env: Map<String, String>,
+}
+
+impl Fixups {
+    /// Whether the build script of the crate should be run
+    pub(super) fn run_buildscript(&self) -> bool {
+        self.buildscript.run.unwrap_or(true)
+    }
+
+    /// Parse the fixups of a crate and resolve them for the given version.
+    fn parse(content: &str, version: &Version) -> Result<Fixups> {
+        let mut fixups: Fixups = toml::from_str(content)?;
+        for (req, scoped) in std::mem::take(&mut fixups.version) {
+            let req = VersionReq::parse(&req)
+                .map_err(|e| anyhow!("invalid version requirement `{}`: {}", req, e))?;
+            if !scoped.version.is_empty() {
+                bail!("`[version.\"{}\"]` cannot have version tables", req);
+            }
+            if req.matches(version) {
+                fixups.merge(scoped);
+            }
+        }
+        Ok(fixups)
+    }
+
+    /// Merge fixups of a narrower scope, whose settings take precedence.
+    fn merge(&mut self, other: Fixups) {
+        self.env.extend(other.env);
+        self.rustc_flags.extend(other.rustc_flags);
+        self.cfgs.extend(other.cfgs);
+        self.extra_srcs.extend(other.extra_srcs);
+        self.buildscript.run = other.buildscript.run.or(self.buildscript.run);
+        self.buildscript.env.extend(other.buildscript.env);
+    }
+
+    /// Apply the fixups to the rules of the crate.
+    pub(super) fn apply(&self, buck_rules: &mut [Rule]) {
+        let rustc_flags: Set<String> = self
+            .rustc_flags
+            .iter()
+            .cloned()
+            .chain(self.cfgs.iter().map(|cfg| format!("--cfg={}", cfg)))
+            .collect();
+        for rule in buck_rules {
+            let (srcs, env, flags) = match rule {
+                Rule::RustLibrary(r) => (&mut r.srcs, &mut r.env, &mut r.rustc_flags),
+                // Build scripts are binaries too, but only get fixups of their own
+                Rule::RustBinary(r) if !r.name.starts_with("build-script-") => {
+                    (&mut r.srcs, &mut r.env, &mut r.rustc_flags)
+                }
+                Rule::BuildscriptRun(r) => {
+                    r.env.extend(self.buildscript.env.clone());
+                    continue;
+                }
+                _ => continue,
+            };
+            srcs.extend(self.extra_srcs.iter().cloned());
+            env.extend(self.env.clone());
+            flags.extend(rustc_flags.iter().cloned());
+        }
+    }
+}
+
+/// Load the fixups of a third-party crate from `third-party/rust/fixups/<crate>/fixups.toml`.
+///
+/// Crates without a fixups file get no fixups, while invalid files are errors.
+pub(super) fn load_fixups(package: &Package) -> Result<Fixups> {
+    read_fixups(&get_fixups_path(&package.name)?, &package.version)
+}
+
+fn read_fixups(path: &Utf8Path, version: &Version) -> Result<Fixups> {
+    if !path.exists() {
+        return Ok(Fixups::default());
+    }
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read `{}`", path))?;
+    Fixups::parse(&content, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buck::{BuildscriptRun, RustBinary, RustLibrary};
+
+    #[test]
+    fn test_apply_fixups() {
+        let content = r#"
+            cfgs = ["tokio_unstable"]
+            extra_srcs = ["//third-party/rust/fixups/demo:data"]
+
+            [env]
+            DEMO_DATA = "data.txt"
+
+            [buildscript.env]
+            DEMO_SYS_STATIC = "1"
+
+            [version."<1.0"]
+            buildscript = { run = false }
+            rustc_flags = ["-Copt-level=3"]
+        "#;
+
+        let fixups = Fixups::parse(content, &Version::new(1, 2, 0)).unwrap();
+        assert!(fixups.run_buildscript());
+        let old_fixups = Fixups::parse(content, &Version::new(0, 9, 0)).unwrap();
+        assert!(!old_fixups.run_buildscript());
+        assert_eq!(old_fixups.rustc_flags, vec!["-Copt-level=3"]);
+
+        // The version scope wins over the crate scope both ways
+        let content = r#"
+            [buildscript]
+            run = false
+
+            [version.">=2"]
+            buildscript = { run = true }
+        "#;
+        assert!(
+            !Fixups::parse(content, &Version::new(1, 0, 0))
+                .unwrap()
+                .run_buildscript()
+        );
+        assert!(
+            Fixups::parse(content, &Version::new(2, 0, 0))
+                .unwrap()
+                .run_buildscript()
+        );
+
+        let mut rules = vec![
+            Rule::RustLibrary(RustLibrary {
+                name: "demo".to_owned(),
+                srcs: Set::from([":vendor".to_owned()]),
+                ..Default::default()
+            }),
+            Rule::RustBinary(RustBinary {
+                name: "build-script-build".to_owned(),
+                ..Default::default()
+            }),
+            Rule::BuildscriptRun(BuildscriptRun {
+                name: "demo-build-script-run".to_owned(),
+                ..Default::default()
+            }),
+        ];
+        fixups.apply(&mut rules);
+
+        let Rule::RustLibrary(library) = &rules[0] else {
+            unreachable!()
+        };
+        assert_eq!(
+            library.srcs,
+            Set::from([
+                ":vendor".to_owned(),
+                "//third-party/rust/fixups/demo:data".to_owned()
+            ])
+        );
+        assert_eq!(library.env["DEMO_DATA"], "data.txt");
+        assert_eq!(
+            library.rustc_flags,
+            Set::from(["--cfg=tokio_unstable".to_owned()])
+        );
+        let Rule::RustBinary(buildscript) = &rules[1] else {
+            unreachable!()
+        };
+        assert!(buildscript.env.is_empty());
+        let Rule::BuildscriptRun(run) = &rules[2] else {
+            unreachable!()
+        };
+        assert_eq!(run.env["DEMO_SYS_STATIC"], "1");
+    }
+
+    #[test]
+    fn test_invalid_fixups() {
+        let version = Version::new(1, 0, 0);
+        let err = Fixups::parse("rustc_flag = [\"--cfg=foo\"]", &version).unwrap_err();
+        assert!(err.to_string().contains("unknown field `rustc_flag`"));
+        let err = Fixups::parse("[buildscript]\nskip = true", &version).unwrap_err();
+        assert!(err.to_string().contains("unknown field `skip`"));
+        assert!(Fixups::parse("[version.\"not a req\"]", &version).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use cargo_metadata::{Node, Package};
+use cargo_util_schemas::manifest::{TomlLintLevel, TomlLints};
+use itertools::Itertools;
+
+use crate::{buck::Rule, buckal_warn, context::BuckalContext, utils::load_manifest};
+
+use super::select::{append_select_to_attr, build_string_list, pretty_print_expr};
+
+/// Load the `[lints]` table of the package, inheriting `[workspace.lints]` if requested.
+fn load_lints(package: &Package, ctx: &BuckalContext) -> Result<TomlLints> {
+    let manifest = load_manifest(&package.manifest_path)?;
+    let Some(lints) = manifest.lints else {
+        return Ok(TomlLints::new());
+    };
+    if !lints.workspace {
+        return Ok(lints.lints);
+    }
+
+    let workspace_manifest = load_manifest(&ctx.workspace_root.join("Cargo.toml"))?;
+    Ok(workspace_manifest
+        .workspace
+        .and_then(|workspace| workspace.lints)
+        .unwrap_or_default())
+}
+
+/// Translate lint tables into `rustc` flags, ordered by priority like Cargo does.
+///
+/// Lints of other tools such as `clippy::` are ignored by `rustc` and only enforced by the tool, so
+/// the `[clippy]` subtarget sharing the `rustc_flags` of the rule applies them.
+fn lint_flags(lints: &TomlLints) -> Vec<String> {
+    let mut flags: Vec<String> = lints
+        .iter()
+        .flat_map(|(tool, tool_lints)| {
+            tool_lints.iter().map(move |(name, lint)| {
+                let name = if tool == "rust" {
+                    name.to_owned()
+                } else {
+                    format!("{}::{}", tool, name)
+                };
+                (lint.priority(), name, lint.level())
+            })
+        })
+        .sorted_by(|(p1, n1, _), (p2, n2, _)| p1.cmp(p2).then_with(|| n1.cmp(n2)))
+        .map(|(_, name, level)| {
+            let flag = match level {
+                TomlLintLevel::Forbid => "-F",
+                TomlLintLevel::Deny => "-D",
+                TomlLintLevel::Warn => "-W",
+                TomlLintLevel::Allow => "-A",
+            };
+            format!("{}{}", flag, name)
+        })
+        .collect();
+
+    // Expected cfgs are declared in the config of the `unexpected_cfgs` lint
+    // See https://doc.rust-lang.org/cargo/reference/build-scripts.html#rustc-check-cfg
+    if let Some(check_cfg) = lints
+        .get("rust")
+        .and_then(|rust| rust.get("unexpected_cfgs"))
+        .and_then(|lint| lint.config())
+        .and_then(|config| config.get("check-cfg"))
+        .and_then(|check_cfg| check_cfg.as_array())
+    {
+        flags.extend(
+            check_cfg
+                .iter()
+                .filter_map(|cfg| cfg.as_str())
+                .map(|cfg| format!("--check-cfg={}", cfg)),
+        );
+    }
+    flags
+}
+
+/// Patch the `rustc_flags` of the rules in the BUCK content of a first-party package with the
+/// levels of its `[lints]` table.
+pub(super) fn patch_lint_flags(
+    mut buck_content: String,
+    buck_rules: &[Rule],
+    node: &Node,
+    ctx: &BuckalContext,
+) -> String {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let lints = match load_lints(package, ctx) {
+        Ok(lints) => lints,
+        Err(e) => {
+            buckal_warn!(
+                "Failed to load the lints of `{}` ({:#}), lint levels are ignored.",
+                package.name,
+                e
+            );
+            return buck_content;
+        }
+    };
+    let flags = lint_flags(&lints);
+    if flags.is_empty() {
+        return buck_content;
+    }
+
+    let mut flags_expr = String::new();
+    pretty_print_expr(&build_string_list(&flags), &mut flags_expr, 4);
+    for rule in buck_rules {
+        let (kind, name) = match rule {
+            Rule::RustLibrary(r) => ("rust_library", &r.name),
+            Rule::RustBinary(r) => ("rust_binary", &r.name),
+            Rule::RustTest(r) => ("rust_test", &r.name),
+            _ => continue,
+        };
+        buck_content =
+            append_select_to_attr(&buck_content, kind, Some(name), "rustc_flags", &flags_expr);
+    }
+    buck_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo_util_schemas::manifest::TomlManifest;
+
+    #[test]
+    fn test_lint_flags() {
+        let manifest: TomlManifest = toml::from_str(
+            r#"
+            [lints.rust]
+            unsafe_code = "forbid"
+            unexpected_cfgs = { level = "warn", check-cfg = ["cfg(tokio_unstable)"] }
+
+            [lints.clippy]
+            all = { level = "deny", priority = -1 }
+            too_many_arguments = "allow"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            lint_flags(&manifest.lints.unwrap().lints),
+            vec![
+                "-Dclippy::all",
+                "-Aclippy::too_many_arguments",
+                "-Wunexpected_cfgs",
+                "-Funsafe_code",
+                "--check-cfg=cfg(tokio_unstable)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap as Map;
+
+use cargo_metadata::{Target, TargetKind};
+use cargo_util_schemas::manifest::{
+    InheritableDependency, TomlDependency, TomlManifest, TomlTarget,
+};
+
+/// Find the manifest entry declaring the given target, if it is declared explicitly.
+fn find_toml_target<'a>(manifest: &'a TomlManifest, target: &Target) -> Option<&'a TomlTarget> {
+    let targets = if target.kind.contains(&TargetKind::Bench) {
+        manifest.bench.as_ref()
+    } else if target.kind.contains(&TargetKind::Test) {
+        manifest.test.as_ref()
+    } else if target.kind.contains(&TargetKind::Example) {
+        manifest.example.as_ref()
+    } else if target.kind.contains(&TargetKind::Bin) {
+        manifest.bin.as_ref()
+    } else {
+        // A package has at most one library, whose name may be implicit
+        return manifest.lib.as_ref();
+    }?;
+    targets
+        .iter()
+        .find(|t| t.name.as_deref() == Some(target.name.as_str()))
+}
+
+/// Whether the given target is compiled with the libtest harness (`harness = true` by default).
+pub(super) fn target_harness(manifest: Option<&TomlManifest>, target: &Target) -> bool {
+    manifest
+        .and_then(|manifest| find_toml_target(manifest, target))
+        .and_then(|t| t.harness)
+        .unwrap_or(true)
+}
+
+/// Get the packages the manifest depends on for their binaries (`artifact = "bin"`), along with
+/// whether their library is a dependency too (`lib = true`).
+///
+/// `cargo metadata` does not tell artifact dependencies apart from regular ones.
+pub(super) fn artifact_dependencies(manifest: &TomlManifest) -> Map<String, bool> {
+    let platform_tables = manifest.target.iter().flat_map(|targets| {
+        targets.values().flat_map(|platform| {
+            [
+                platform.dependencies.as_ref(),
+                platform.dev_dependencies(),
+                platform.build_dependencies(),
+            ]
+        })
+    });
+    [
+        manifest.dependencies.as_ref(),
+        manifest.dev_dependencies(),
+        manifest.build_dependencies(),
+    ]
+    .into_iter()
+    .chain(platform_tables)
+    .flatten()
+    .flatten()
+    .filter_map(|(name, dep)| {
+        let InheritableDependency::Value(TomlDependency::Detailed(detail)) = dep else {
+            return None;
+        };
+        let artifact = detail.artifact.as_ref()?;
+        if !artifact
+            .iter()
+            .any(|kind| kind == "bin" || kind.starts_with("bin:"))
+        {
+            return None;
+        }
+        let package = detail.package.as_ref().unwrap_or(name);
+        Some((package.to_string(), detail.lib.unwrap_or(false)))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_target(name: &str, kind: TargetKind) -> Target {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "kind": [kind],
+            "crate_types": [],
+            "required_features": [],
+            "src_path": "/tmp/dummy.rs",
+            "edition": "2021",
+            "doctest": true,
+            "test": true
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_target_harness() {
+        let manifest: TomlManifest = toml::from_str(
+            r#"
+            [package]
+            name = "foo"
+            version = "0.1.0"
+
+            [[bench]]
+            name = "throughput"
+            harness = false
+
+            [lib]
+            harness = false
+
+            [[test]]
+            name = "datatest"
+            harness = false
+            "#,
+        )
+        .unwrap();
+
+        let bench = mock_target("throughput", TargetKind::Bench);
+        let test = mock_target("datatest", TargetKind::Test);
+        let implicit = mock_target("smoke", TargetKind::Bench);
+        // Names are looked up per target kind
+        let same_name_test = mock_target("throughput", TargetKind::Test);
+
+        assert!(!target_harness(Some(&manifest), &bench));
+        assert!(!target_harness(Some(&manifest), &test));
+        assert!(target_harness(Some(&manifest), &implicit));
+        assert!(target_harness(Some(&manifest), &same_name_test));
+        assert!(target_harness(None, &bench));
+        assert!(!target_harness(
+            Some(&manifest),
+            &mock_target("foo", TargetKind::Lib)
+        ));
+    }
+
+    #[test]
+    fn test_artifact_dependencies() {
+        let manifest: TomlManifest = toml::from_str(
+            r#"
+            [package]
+            name = "foo"
+            version = "0.1.0"
+
+            [dependencies]
+            serde = "1"
+            tool = { path = "../tool", artifact = "bin", lib = true }
+
+            [build-dependencies]
+            gen = { version = "0.4", package = "protoc-gen", artifact = ["bin:protoc-gen", "cdylib"] }
+
+            [target.'cfg(unix)'.dev-dependencies]
+            shim = { path = "../shim", artifact = "staticlib" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            artifact_dependencies(&manifest),
+            Map::from([("protoc-gen".to_owned(), false), ("tool".to_owned(), true)])
+        );
+    }
+}
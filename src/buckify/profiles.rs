@@ -0,0 +1,245 @@
+use cargo_metadata::{Node, Package, TargetKind};
+
+use crate::{
+    buck::Rule,
+    buckal_warn,
+    context::BuckalContext,
+    profiles::{
+        custom_profiles, package_profile, profile_constraint, profile_rustc_flags, resolve_profile,
+    },
+};
+
+use super::{
+    actions::is_third_party,
+    emit::get_exec_name,
+    select::{append_select_to_attr, build_select, build_string_list, pretty_print_expr},
+};
+
+/// `rustc` flags of every profile with a mode, keyed by the constraint of the mode.
+struct ProfileFlags {
+    constraint: Option<String>,
+    flags: Vec<String>,
+    binary_flags: Vec<String>,
+}
+
+fn collect_profile_flags(node: &Node, ctx: &BuckalContext) -> Vec<ProfileFlags> {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let is_member = !is_third_party(package);
+
+    // Undeclared `test` and `bench` profiles build with the `debug` and `release` modes
+    ["dev", "release"]
+        .into_iter()
+        .chain(custom_profiles(&ctx.profiles))
+        .filter_map(|name| {
+            let profile = resolve_profile(name, &ctx.profiles)
+                .inspect_err(|e| buckal_warn!("Ignoring profile `{}`: {}", name, e))
+                .ok()?;
+            let (flags, binary_flags) =
+                profile_rustc_flags(&package_profile(&profile, package, is_member));
+            Some(ProfileFlags {
+                constraint: profile_constraint(name),
+                flags,
+                binary_flags,
+            })
+        })
+        .collect()
+}
+
+/// Render a `select()` adding the flags of each profile on its mode.
+fn render_profile_select(profiles: &[ProfileFlags], binary: bool) -> Option<String> {
+    let flags_of = |p: &ProfileFlags| {
+        let mut flags = p.flags.clone();
+        if binary {
+            flags.extend(p.binary_flags.iter().cloned());
+        }
+        flags
+    };
+    if profiles.iter().all(|p| flags_of(p).is_empty()) {
+        return None;
+    }
+
+    let default = profiles
+        .iter()
+        .find(|p| p.constraint.is_none())
+        .map(flags_of)
+        .unwrap_or_default();
+    let lists: Vec<(&str, _)> = profiles
+        .iter()
+        .filter_map(|p| {
+            let constraint = p.constraint.as_deref()?;
+            Some((constraint, build_string_list(&flags_of(p))))
+        })
+        .chain(std::iter::once(("DEFAULT", build_string_list(&default))))
+        .collect();
+
+    let mut out = String::new();
+    pretty_print_expr(&build_select(&lists), &mut out, 4);
+    Some(out)
+}
+
+/// Get the kind and name of the rules of the package built for the target platform.
+///
+/// Build scripts, proc-macros and their exec dependencies are built for the exec platform, like
+/// Cargo builds them for the host when cross-compiling.
+pub(super) fn target_rules<'a>(
+    buck_rules: &'a [Rule],
+    package: &Package,
+) -> Vec<(&'static str, &'a str)> {
+    let build_scripts: Vec<&str> = package
+        .targets
+        .iter()
+        .filter(|t| t.kind.contains(&TargetKind::CustomBuild))
+        .map(|t| t.name.as_str())
+        .collect();
+    let exec_library = get_exec_name(&package.name);
+
+    buck_rules
+        .iter()
+        .filter_map(|rule| match rule {
+            Rule::RustLibrary(r) if r.proc_macro == Some(true) || r.name == exec_library => None,
+            Rule::RustLibrary(r) => Some(("rust_library", r.name.as_str())),
+            Rule::RustTest(r) => Some(("rust_test", r.name.as_str())),
+            Rule::RustBinary(r) if build_scripts.contains(&r.name.as_str()) => None,
+            Rule::RustBinary(r) => Some(("rust_binary", r.name.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Patch the `rustc_flags` of the rules in the BUCK content of the package with the settings of
+/// the Cargo profile of each mode.
+///
+/// Rules built for the exec platform are left to the defaults of the mode, like Cargo's
+/// `build-override` does.
+pub(super) fn patch_profile_flags(
+    mut buck_content: String,
+    buck_rules: &[Rule],
+    node: &Node,
+    ctx: &BuckalContext,
+) -> String {
+    let package = ctx.packages_map.get(&node.id).unwrap();
+    let profiles = collect_profile_flags(node, ctx);
+    let library_select = render_profile_select(&profiles, false);
+    let binary_select = render_profile_select(&profiles, true);
+
+    for (kind, name) in target_rules(buck_rules, package) {
+        let select_expr = if kind == "rust_binary" {
+            &binary_select
+        } else {
+            &library_select
+        };
+        if let Some(select_expr) = select_expr {
+            buck_content =
+                append_select_to_attr(&buck_content, kind, Some(name), "rustc_flags", select_expr);
+        }
+    }
+    buck_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buck::{RustBinary, RustLibrary};
+    use crate::context::mock_context;
+    use cargo_util_schemas::manifest::TomlManifest;
+    use indoc::indoc;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_patch_profile_flags() {
+        let id = "registry+https://github.com/rust-lang/crates.io-index#demo@0.1.0";
+        let package: Package = serde_json::from_value(serde_json::json!({
+            "name": "demo",
+            "version": "0.1.0",
+            "id": id,
+            "source": "registry+https://github.com/rust-lang/crates.io-index",
+            "dependencies": [],
+            "targets": [{
+                "name": "build-script-build",
+                "kind": ["custom-build"],
+                "crate_types": ["bin"],
+                "required_features": [],
+                "src_path": "/tmp/demo/build.rs",
+                "edition": "2021",
+                "doctest": false,
+                "test": false
+            }],
+            "features": {},
+            "manifest_path": "/tmp/demo/Cargo.toml",
+            "edition": "2021"
+        }))
+        .unwrap();
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "deps": [],
+            "dependencies": [],
+            "features": []
+        }))
+        .unwrap();
+        let manifest: TomlManifest = toml::from_str(indoc! {r#"
+            [profile.release]
+            panic = "abort"
+
+            [profile.release.package."*"]
+            codegen-units = 1
+        "#})
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map: HashMap::from([(package.id.clone(), package)]),
+            profiles: manifest
+                .profile
+                .unwrap()
+                .get_all()
+                .iter()
+                .map(|(name, profile)| (name.to_string(), profile.clone()))
+                .collect(),
+            ..mock_context()
+        };
+
+        let rules = vec![
+            Rule::RustLibrary(RustLibrary {
+                name: "demo".to_owned(),
+                ..Default::default()
+            }),
+            Rule::RustBinary(RustBinary {
+                name: "build-script-build".to_owned(),
+                ..Default::default()
+            }),
+        ];
+        let input = indoc! {r#"
+            rust_library(
+                name = "demo",
+                rustc_flags = ["@$(location :manifest[env_flags])"],
+            )
+
+            rust_binary(
+                name = "build-script-build",
+                rustc_flags = ["@$(location :manifest[env_flags])"],
+            )
+        "#};
+
+        // `panic` is left to binaries, and build scripts keep the defaults of the mode
+        let expected = indoc! {r#"
+            rust_library(
+                name = "demo",
+                rustc_flags = ["@$(location :manifest[env_flags])"] + select({
+                    "buckal//config/mode:release": [
+                        "-Ccodegen-units=1",
+                    ],
+                    "DEFAULT": [],
+                }),
+            )
+
+            rust_binary(
+                name = "build-script-build",
+                rustc_flags = ["@$(location :manifest[env_flags])"],
+            )
+        "#};
+
+        assert_eq!(
+            patch_profile_flags(input.to_owned(), &rules, &node, &ctx),
+            expected
+        );
+    }
+}
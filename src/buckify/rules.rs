@@ -1,72 +1,109 @@
-use std::{collections::BTreeSet as Set, vec};
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    vec,
+};
 
-use cargo_metadata::{Node, Package, camino::Utf8PathBuf};
+use anyhow::Result;
+use cargo_metadata::{CrateType, Node, Package, Target, camino::Utf8PathBuf};
 use cargo_util_schemas::core::{PackageIdSpec, SourceKind};
+use cargo_util_schemas::manifest::TomlManifest;
 use itertools::Itertools;
 
 use crate::{
-    buck::{Load, Rule, RustRule},
-    buckal_error, buckal_note,
+    RUST_GIT_REPOS_ROOT, TARGET_TMPDIR,
+    assets::GIT_FETCH_RULE,
+    buck::{Load, Rule, RustRule, RustTest},
+    buckal_error, buckal_note, buckal_warn,
+    config::RequiredFeaturesPolicy,
     context::BuckalContext,
-    utils::{UnwrapOrExit, get_vendor_dir},
+    utils::{
+        UnwrapOrExit, get_buck2_root, get_git_repo_path_relative, get_vendor_dir, load_manifest,
+        unmet_required_features,
+    },
+    vendor::{copy_path_sources, vendor_crate, vendor_git_package},
 };
 
+use super::deps::{get_artifact_bin_name, is_artifact_dependency};
 use super::emit::{
-    emit_buildscript_build, emit_buildscript_run, emit_cargo_manifest, emit_filegroup,
-    emit_git_fetch, emit_http_archive, emit_rust_binary, emit_rust_library, emit_rust_test,
-    patch_with_buildscript,
+    emit_buildscript_build, emit_buildscript_run, emit_cargo_manifest, emit_exec_rust_library,
+    emit_filegroup, emit_git_alias, emit_git_fetch, emit_http_archive, emit_rust_binary,
+    emit_rust_library, emit_rust_test, exports_sources, extra_crate_types,
+    get_buildscript_run_name, get_crate_type_name, get_exec_name, get_vendor_target,
+    git_checkout_root, git_sub_dir, patch_with_buildscript, set_crate_type,
+};
+use super::features::{
+    RequiredFeatures, emit_feature_settings, exec_features, required_features,
+    strip_default_only_features,
 };
+use super::fixups::load_fixups;
+use super::manifest::target_harness;
 
 /// Buckifies a third-party dependency into a list of BUCK rules.
 ///
-/// This includes generating rules for the library target, and if a build script is present, also generating rules for the build script and patching the library rule accordingly.
+/// This includes generating rules for the library target (or the binary targets of packages without one), and if a build script is present, also generating rules for the build script and patching the library rule accordingly.
 pub fn buckify_dep_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
     let package = ctx.packages_map.get(&node.id).unwrap().to_owned();
+    let fixups =
+        load_fixups(&package).unwrap_or_exit_ctx(format!("invalid fixups for `{}`", package.name));
 
     // emit buck rules for lib target
     let mut buck_rules: Vec<Rule> = Vec::new();
 
     let manifest_dir = package.manifest_path.parent().unwrap().to_owned();
-    let lib_target = package
-        .targets
-        .iter()
-        .find(|t| {
-            t.kind.contains(&cargo_metadata::TargetKind::Lib)
-                || t.kind.contains(&cargo_metadata::TargetKind::CDyLib)
-                || t.kind.contains(&cargo_metadata::TargetKind::DyLib)
-                || t.kind.contains(&cargo_metadata::TargetKind::RLib)
-                || t.kind.contains(&cargo_metadata::TargetKind::StaticLib)
-                || t.kind.contains(&cargo_metadata::TargetKind::ProcMacro)
-        })
-        .expect("No library target found");
+    // Git sources are fetched as a whole repository, so crate roots are relative to its checkout,
+    // unless they are vendored like path sources
+    let is_git = !ctx.repo_config.vendor
+        && package
+            .source
+            .as_ref()
+            .is_some_and(|source| source.repr.starts_with("git+"));
+    let vendor_root = match git_checkout_root(&manifest_dir) {
+        Some(checkout_root) if is_git => checkout_root.to_owned(),
+        _ => manifest_dir.clone(),
+    };
+    let lib_target = package.targets.iter().find(|t| {
+        t.kind.contains(&cargo_metadata::TargetKind::Lib)
+            || t.kind.contains(&cargo_metadata::TargetKind::CDyLib)
+            || t.kind.contains(&cargo_metadata::TargetKind::DyLib)
+            || t.kind.contains(&cargo_metadata::TargetKind::RLib)
+            || t.kind.contains(&cargo_metadata::TargetKind::StaticLib)
+            || t.kind.contains(&cargo_metadata::TargetKind::ProcMacro)
+    });
 
     // Generate rules to vendor the dependency source code
     let package_id_spec =
         PackageIdSpec::parse(&package.id.repr).unwrap_or_exit_ctx("failed to parse package ID");
 
     match package_id_spec.kind().unwrap() {
+        SourceKind::Registry if ctx.repo_config.vendor => {
+            // Sources are unpacked next to the BUCK file for offline builds
+            let filegroup = emit_filegroup();
+            buck_rules.push(Rule::FileGroup(filegroup));
+        }
         SourceKind::Registry => {
             let http_archive = emit_http_archive(&package, ctx);
             buck_rules.push(Rule::HttpArchive(http_archive));
         }
         SourceKind::Path => {
-            buckal_error!(
-                "Local path ({}) is not supported for third-party packages.",
-                package_id_spec.url().unwrap().path()
-            );
-            buckal_note!(
-                "Please consider importing `{}` with registry or git source instead, or if it's a local package, move it to the workspace and it will be treated as a root package.",
-                package.name
-            );
-            std::process::exit(1);
+            // Sources outside of the Buck2 project are copied next to the BUCK file
+            let filegroup = emit_filegroup();
+            buck_rules.push(Rule::FileGroup(filegroup));
+        }
+        SourceKind::Git(_) if ctx.repo_config.vendor => {
+            // Sources are copied from the Cargo checkout next to the BUCK file for offline builds
+            let filegroup = emit_filegroup();
+            buck_rules.push(Rule::FileGroup(filegroup));
         }
         SourceKind::Git(_) => {
-            let git_fetch = emit_git_fetch(&package);
-            buck_rules.push(Rule::GitFetch(git_fetch));
+            // Sources come from the `git_fetch` rule shared by the crates of the repository
+            let git_alias = emit_git_alias(&package);
+            buck_rules.push(Rule::Alias(git_alias));
         }
         _ => {
             buckal_error!("Unsupported source type for package `{}`.", package.name);
-            buckal_note!("Only registry and git sources are supported for third-party packages.");
+            buckal_note!(
+                "Only registry, git and path sources are supported for third-party packages."
+            );
             std::process::exit(1);
         }
     }
@@ -74,45 +111,115 @@ pub fn buckify_dep_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
     let cargo_manifest = emit_cargo_manifest();
     buck_rules.push(Rule::CargoManifest(cargo_manifest));
 
-    let rust_library = emit_rust_library(
-        &package,
-        node,
-        lib_target,
-        &manifest_dir,
-        &package.name,
-        ctx,
-    );
+    if let Some(lib_target) = lib_target {
+        let rust_library =
+            emit_rust_library(&package, node, lib_target, &vendor_root, &package.name, ctx);
 
-    buck_rules.push(Rule::RustLibrary(rust_library));
+        buck_rules.push(Rule::RustLibrary(rust_library));
 
-    // Check if the package has a build script
+        // Build dependencies and proc-macros may need the package with other features
+        if let Some(exec_library) =
+            emit_exec_rust_library(&package, node, lib_target, &vendor_root, &package.name, ctx)
+        {
+            buck_rules.push(Rule::RustLibrary(exec_library));
+        }
+    }
+
+    // Packages without a library (tools, artifact dependencies) are built as binaries, and so are
+    // the binaries of packages depended on with `artifact = "bin"` next to their library
+    if lib_target.is_none() || is_artifact_dependency(&package, ctx) {
+        let bin_targets = package
+            .targets
+            .iter()
+            .filter(|t| t.kind.contains(&cargo_metadata::TargetKind::Bin))
+            .filter(|t| unmet_required_features(t, &node.features).is_empty())
+            .collect::<Vec<_>>();
+
+        if bin_targets.is_empty() && lib_target.is_none() {
+            buckal_error!(
+                "Package `{}` has neither a library nor a binary target.",
+                package.name
+            );
+            std::process::exit(1);
+        }
+
+        for bin_target in bin_targets {
+            let mut rust_binary = emit_rust_binary(
+                &package,
+                node,
+                bin_target,
+                &vendor_root,
+                &get_artifact_bin_name(&package, bin_target),
+                ctx,
+            );
+            if lib_target.is_some() {
+                // Cargo links the library of the package into its binaries
+                rust_binary.deps_mut().insert(format!(":{}", package.name));
+            }
+
+            buck_rules.push(Rule::RustBinary(rust_binary));
+        }
+    }
+
+    // Check if the package has a build script, unless fixups disable it
     let custom_build_target = package
         .targets
         .iter()
-        .find(|t| t.kind.contains(&cargo_metadata::TargetKind::CustomBuild));
+        .find(|t| t.kind.contains(&cargo_metadata::TargetKind::CustomBuild))
+        .filter(|_| fixups.run_buildscript());
 
     if let Some(build_target) = custom_build_target {
-        // Patch the rust_library rule to support build scripts
+        // Patch the rust_library rule to support build scripts, the exec variant runs its own
+        let run_name = get_buildscript_run_name(build_target);
+        let exec_run_name = get_exec_name(&run_name);
+        let exec_library_name = get_exec_name(&package.name);
         for rule in &mut buck_rules {
+            let rule_run_name = match rule {
+                Rule::RustLibrary(rust_library) if rust_library.name == exec_library_name => {
+                    &exec_run_name
+                }
+                _ => &run_name,
+            };
             if let Some(rust_rule) = rule.as_rust_rule_mut() {
-                patch_with_buildscript(rust_rule, build_target);
+                patch_with_buildscript(rust_rule, rule_run_name);
             }
         }
 
         // create the build script rule
         let buildscript_build =
-            emit_buildscript_build(build_target, &package, node, &manifest_dir, ctx);
+            emit_buildscript_build(build_target, &package, node, &vendor_root, ctx);
         buck_rules.push(Rule::RustBinary(buildscript_build));
 
         // create the build script run rule
         let buildscript_run = emit_buildscript_run(&package, node, build_target, ctx);
         buck_rules.push(Rule::BuildscriptRun(buildscript_run));
+
+        if let Some(features) = exec_features(node, ctx) {
+            let mut exec_buildscript_run = emit_buildscript_run(&package, node, build_target, ctx);
+            exec_buildscript_run.name = exec_run_name;
+            exec_buildscript_run.features = features.clone();
+            buck_rules.push(Rule::BuildscriptRun(exec_buildscript_run));
+        }
+    }
+
+    // The manifest of a package in a subdirectory of its git repository is read from there
+    if let Some(sub_dir) = git_sub_dir(&package).filter(|_| is_git) {
+        let manifest_target = format!("{}[{}]", get_vendor_target(), sub_dir);
+        for rule in &mut buck_rules {
+            match rule {
+                Rule::CargoManifest(r) => r.vendor = manifest_target.clone(),
+                Rule::BuildscriptRun(r) => r.manifest_dir = manifest_target.clone(),
+                _ => {}
+            }
+        }
     }
 
+    fixups.apply(&mut buck_rules);
+
     buck_rules
 }
 
-/// Buckifies workspace package into a list of BUCK rules, including rules for all targets (bin, lib, test) and handling build scripts if present.
+/// Buckifies workspace package into a list of BUCK rules, including rules for all targets (bin, lib, test, example, bench) and handling build scripts if present.
 pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
     let package = ctx.packages_map.get(&node.id).unwrap().to_owned();
 
@@ -141,12 +248,54 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
         .filter(|t| t.kind.contains(&cargo_metadata::TargetKind::Test))
         .collect::<Vec<_>>();
 
+    let example_targets = package
+        .targets
+        .iter()
+        .filter(|t| t.kind.contains(&cargo_metadata::TargetKind::Example))
+        .collect::<Vec<_>>();
+
+    let bench_targets = package
+        .targets
+        .iter()
+        .filter(|t| t.kind.contains(&cargo_metadata::TargetKind::Bench))
+        .collect::<Vec<_>>();
+
+    // Some target settings (e.g. `harness`) are only available from the manifest itself
+    let toml_manifest = load_manifest(&package.manifest_path)
+        .inspect_err(|e| buckal_warn!("{:#}", e))
+        .ok();
+
+    // Label of the package library, which Cargo links into tests and examples implicitly
+    let lib_label = lib_targets.first().map(|lib_target| {
+        if bin_targets.iter().any(|b| b.name == lib_target.name) {
+            format!(":{}-lib", lib_target.name)
+        } else {
+            format!(":{}", lib_target.name)
+        }
+    });
+
+    // Cargo exposes every binary of the package and a scratch directory to integration tests and benches
+    let mut integration_env: Map<String, String> = bin_targets
+        .iter()
+        .filter(|b| required_features(b, node, ctx) == RequiredFeatures::Enabled)
+        .map(|b| {
+            (
+                format!("CARGO_BIN_EXE_{}", b.name),
+                format!("$(location :{})", b.name),
+            )
+        })
+        .collect();
+    integration_env.insert("CARGO_TARGET_TMPDIR".to_owned(), TARGET_TMPDIR.to_owned());
+
     let mut buck_rules: Vec<Rule> = Vec::new();
 
     let manifest_dir = package.manifest_path.parent().unwrap().to_owned();
 
-    // emit filegroup rule for vendor
-    let filegroup = emit_filegroup();
+    // emit filegroup rule for vendor, also read by the targets of other packages built from it
+    let mut filegroup = emit_filegroup();
+    if exports_sources(&package, ctx) {
+        filegroup.visibility = Set::from(["PUBLIC".to_owned()]);
+    }
     buck_rules.push(Rule::FileGroup(filegroup));
 
     let cargo_manifest = emit_cargo_manifest();
@@ -154,6 +303,10 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
 
     // emit buck rules for bin targets
     for bin_target in &bin_targets {
+        if skip_target(bin_target, node, ctx) {
+            continue;
+        }
+
         let buckal_name = bin_target.name.to_owned();
 
         let mut rust_binary =
@@ -165,8 +318,31 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
                 .deps_mut()
                 .insert(format!(":{}-lib", bin_target.name));
         }
+        mark_incompatible(&mut rust_binary, bin_target, node, ctx);
 
         buck_rules.push(Rule::RustBinary(rust_binary));
+
+        if !ctx.repo_config.ignore_tests && bin_target.test {
+            // If the bin target has inline tests, emit a rust_test rule sharing its crate root
+            let mut rust_test = emit_rust_test(
+                &package,
+                node,
+                bin_target,
+                &manifest_dir,
+                &get_bin_test_name(&bin_target.name),
+                ctx,
+            );
+
+            if lib_targets.iter().any(|l| l.name == bin_target.name) {
+                rust_test
+                    .deps_mut()
+                    .insert(format!(":{}-lib", bin_target.name));
+            }
+            set_harness(&mut rust_test, toml_manifest.as_ref(), bin_target);
+            mark_incompatible(&mut rust_test, bin_target, node, ctx);
+
+            buck_rules.push(Rule::RustTest(rust_test));
+        }
     }
 
     // emit buck rules for lib targets
@@ -177,15 +353,36 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
             lib_target.name.to_owned()
         };
 
-        let rust_library =
+        let mut rust_library =
             emit_rust_library(&package, node, lib_target, &manifest_dir, &buckal_name, ctx);
 
+        if !ctx.repo_config.ignore_tests {
+            // Doctests are run by `buck2 test` on the library rule itself
+            rust_library.doctests = Some(lib_target.doctest);
+        }
+
         buck_rules.push(Rule::RustLibrary(rust_library));
 
+        // Each additional native library type (e.g. a cdylib next to the rlib) gets its own rule
+        for crate_type in extra_crate_types(lib_target) {
+            let mut rust_library = emit_rust_library(
+                &package,
+                node,
+                lib_target,
+                &manifest_dir,
+                &get_crate_type_name(&buckal_name, crate_type),
+                ctx,
+            );
+            set_crate_type(&mut rust_library, crate_type);
+
+            buck_rules.push(Rule::RustLibrary(rust_library));
+        }
+
         if !ctx.repo_config.ignore_tests && lib_target.test {
             // If the library target has inline tests, emit a rust_test rule for it
-            let rust_test =
+            let mut rust_test =
                 emit_rust_test(&package, node, lib_target, &manifest_dir, "unittest", ctx);
+            set_harness(&mut rust_test, toml_manifest.as_ref(), lib_target);
 
             buck_rules.push(Rule::RustTest(rust_test));
         }
@@ -194,6 +391,10 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
     // emit buck rules for integration test
     if !ctx.repo_config.ignore_tests {
         for test_target in &test_targets {
+            if skip_target(test_target, node, ctx) {
+                continue;
+            }
+
             let buckal_name = test_target.name.to_owned();
 
             let mut rust_test = emit_rust_test(
@@ -205,26 +406,99 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
                 ctx,
             );
 
-            let package_name = package.name.replace("-", "_");
-            let mut lib_alias = false;
-            if bin_targets.iter().any(|b| b.name == package_name) {
-                lib_alias = true;
-                rust_test.env_mut().insert(
-                    format!("CARGO_BIN_EXE_{}", package_name),
-                    format!("$(location :{})", package_name),
-                );
+            rust_test.env_mut().extend(integration_env.clone());
+            if let Some(lib_label) = &lib_label {
+                rust_test.deps_mut().insert(lib_label.to_owned());
             }
-            if lib_targets.iter().any(|l| l.name == package_name) {
-                if lib_alias {
-                    rust_test
-                        .deps_mut()
-                        .insert(format!(":{}-lib", package_name));
-                } else {
-                    rust_test.deps_mut().insert(format!(":{}", package_name));
-                }
+            set_harness(&mut rust_test, toml_manifest.as_ref(), test_target);
+            mark_incompatible(&mut rust_test, test_target, node, ctx);
+
+            buck_rules.push(Rule::RustTest(rust_test));
+        }
+    }
+
+    // emit buck rules for example targets
+    for example_target in &example_targets {
+        if skip_target(example_target, node, ctx) {
+            continue;
+        }
+
+        let buckal_name = get_example_name(&example_target.name);
+
+        // Examples are executables unless they declare a library `crate-type`
+        let is_library = !example_target.crate_types.is_empty()
+            && !example_target.crate_types.contains(&CrateType::Bin);
+
+        let mut rust_rule = if is_library {
+            Rule::RustLibrary(emit_rust_library(
+                &package,
+                node,
+                example_target,
+                &manifest_dir,
+                &buckal_name,
+                ctx,
+            ))
+        } else {
+            Rule::RustBinary(emit_rust_binary(
+                &package,
+                node,
+                example_target,
+                &manifest_dir,
+                &buckal_name,
+                ctx,
+            ))
+        };
+
+        if let Some(rust_rule) = rust_rule.as_rust_rule_mut() {
+            if let Some(lib_label) = &lib_label {
+                rust_rule.deps_mut().insert(lib_label.to_owned());
             }
+            mark_incompatible(rust_rule, example_target, node, ctx);
+        }
+
+        buck_rules.push(rust_rule);
+    }
 
+    // emit buck rules for bench targets
+    for bench_target in &bench_targets {
+        if skip_target(bench_target, node, ctx) {
+            continue;
+        }
+
+        let buckal_name = get_bench_name(&bench_target.name);
+
+        if target_harness(toml_manifest.as_ref(), bench_target) {
+            // libtest `#[bench]` functions need to be compiled with the test harness
+            let mut rust_test = emit_rust_test(
+                &package,
+                node,
+                bench_target,
+                &manifest_dir,
+                &buckal_name,
+                ctx,
+            );
+            rust_test.env_mut().extend(integration_env.clone());
+            if let Some(lib_label) = &lib_label {
+                rust_test.deps_mut().insert(lib_label.to_owned());
+            }
+            mark_incompatible(&mut rust_test, bench_target, node, ctx);
             buck_rules.push(Rule::RustTest(rust_test));
+        } else {
+            // Benches with `harness = false` (e.g. criterion) provide their own `main`
+            let mut rust_binary = emit_rust_binary(
+                &package,
+                node,
+                bench_target,
+                &manifest_dir,
+                &buckal_name,
+                ctx,
+            );
+            rust_binary.env_mut().extend(integration_env.clone());
+            if let Some(lib_label) = &lib_label {
+                rust_binary.deps_mut().insert(lib_label.to_owned());
+            }
+            mark_incompatible(&mut rust_binary, bench_target, node, ctx);
+            buck_rules.push(Rule::RustBinary(rust_binary));
         }
     }
 
@@ -238,7 +512,7 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
         // Patch the rust_library and rust_binary rules to support build scripts
         for rule in &mut buck_rules {
             if let Some(rust_rule) = rule.as_rust_rule_mut() {
-                patch_with_buildscript(rust_rule, build_target);
+                patch_with_buildscript(rust_rule, &get_buildscript_run_name(build_target));
             }
         }
 
@@ -252,20 +526,133 @@ pub fn buckify_root_node(node: &Node, ctx: &BuckalContext) -> Vec<Rule> {
         buck_rules.push(Rule::BuildscriptRun(buildscript_run));
     }
 
+    // Features can be switched at build time through `config_setting` rules
+    strip_default_only_features(&mut buck_rules, node, ctx);
+    buck_rules.extend(emit_feature_settings(node, ctx));
+
     buck_rules
 }
 
+/// Whether the target should be left out because its `required-features` cannot be enabled.
+///
+/// Targets whose `required-features` can be selected at build time always get a rule, made
+/// incompatible unless they are selected by [`patch_required_features`](super::features::patch_required_features).
+fn skip_target(target: &Target, node: &Node, ctx: &BuckalContext) -> bool {
+    ctx.repo_config.required_features == RequiredFeaturesPolicy::Skip
+        && required_features(target, node, ctx) == RequiredFeatures::Disabled
+}
+
+/// Mark the rule as incompatible with every platform if the target's `required-features` cannot be enabled.
+fn mark_incompatible(
+    rust_rule: &mut dyn RustRule,
+    target: &Target,
+    node: &Node,
+    ctx: &BuckalContext,
+) {
+    if required_features(target, node, ctx) == RequiredFeatures::Disabled {
+        rust_rule
+            .target_compatible_with_mut()
+            .insert("config//:none".to_owned());
+    }
+}
+
+/// Build the test without the libtest harness if the target sets `harness = false`.
+///
+/// Such tests provide their own `main` (e.g. datatest or libtest-mimic suites), so Buck has to run them as plain executables.
+fn set_harness(rust_test: &mut RustTest, manifest: Option<&TomlManifest>, target: &Target) {
+    if !target_harness(manifest, target) {
+        rust_test.framework = Some(false);
+    }
+}
+
+/// Get the name of the rule running the unit tests of a bin target.
+pub fn get_bin_test_name(name: &str) -> String {
+    format!("{name}-unittest")
+}
+
+/// Get the name of the rule emitted for an example target.
+///
+/// Examples live in their own namespace in Cargo, so a suffix keeps them from colliding with bin and lib rules.
+pub fn get_example_name(name: &str) -> String {
+    format!("{name}-example")
+}
+
+/// Get the name of the rule emitted for a bench target.
+pub fn get_bench_name(name: &str) -> String {
+    format!("{name}-bench")
+}
+
 /// Vendors the package sources to `third-party` and returns the path.
-pub fn vendor_package(package: &Package) -> Utf8PathBuf {
+pub fn vendor_package(package: &Package, ctx: &BuckalContext) -> Utf8PathBuf {
     let vendor_dir =
         get_vendor_dir(&package.id).unwrap_or_exit_ctx("failed to get vendor directory");
     if !vendor_dir.exists() {
         std::fs::create_dir_all(&vendor_dir).expect("Failed to create target directory");
     }
 
+    let package_id_spec =
+        PackageIdSpec::parse(&package.id.repr).unwrap_or_exit_ctx("failed to parse package ID");
+    match package_id_spec.kind() {
+        Some(SourceKind::Path) => {
+            let package_dir = package.manifest_path.parent().unwrap();
+            copy_path_sources(package_dir, &vendor_dir).unwrap_or_exit_ctx(format!(
+                "failed to copy the sources of `{}` from `{}`",
+                package.name, package_dir
+            ));
+        }
+        Some(SourceKind::Git(_)) if ctx.repo_config.vendor => {
+            vendor_git_package(package, &vendor_dir).unwrap_or_exit_ctx(format!(
+                "failed to vendor the sources of `{} v{}`",
+                package.name, package.version
+            ));
+        }
+        Some(SourceKind::Git(_)) => {
+            write_git_repo(package, ctx).unwrap_or_exit_ctx(format!(
+                "failed to write the git repository rule of `{}`",
+                package.name
+            ));
+        }
+        Some(SourceKind::Registry) if ctx.repo_config.vendor => {
+            let checksum = ctx
+                .checksums_map
+                .get(&format!("{}-{}", package.name, package.version))
+                .unwrap();
+            let cargo_vendor_dir = ctx
+                .repo_config
+                .vendor_dir
+                .as_ref()
+                .map(|dir| ctx.workspace_root.join(dir));
+            vendor_crate(package, checksum, cargo_vendor_dir.as_deref(), &vendor_dir)
+                .unwrap_or_exit_ctx(format!(
+                    "failed to vendor the sources of `{} v{}`",
+                    package.name, package.version
+                ));
+        }
+        _ => {}
+    }
+
     vendor_dir
 }
 
+/// Write the `git_fetch` rule shared by the crates of the git repository of a package.
+fn write_git_repo(package: &Package, ctx: &BuckalContext) -> Result<()> {
+    let buck2_root = get_buck2_root()?;
+    let source = &package.source.as_ref().unwrap().repr;
+    let repo_dir = buck2_root.join(get_git_repo_path_relative(source)?);
+    std::fs::create_dir_all(&repo_dir)?;
+    std::fs::write(
+        buck2_root.join(RUST_GIT_REPOS_ROOT).join("git_fetch.bzl"),
+        GIT_FETCH_RULE,
+    )?;
+
+    let git_fetch = emit_git_fetch(package, ctx);
+    std::fs::write(
+        repo_dir.join("BUCK"),
+        gen_buck_content(&[Rule::GitFetch(git_fetch)]),
+    )?;
+    Ok(())
+}
+
 /// Generate the content of the BUCK file based on the given rules, including conditional load statements for used rule types.
 pub fn gen_buck_content(rules: &[Rule]) -> String {
     // Analyze which rule types are present to build conditional load statements
@@ -274,9 +661,11 @@ pub fn gen_buck_content(rules: &[Rule]) -> String {
     let mut has_rust_binary = false;
     let mut has_rust_test = false;
     let mut has_buildscript_run = false;
+    let mut has_git_fetch = false;
 
     for rule in rules {
         match rule {
+            Rule::GitFetch(_) => has_git_fetch = true,
             Rule::CargoManifest(_) => has_cargo_manifest = true,
             Rule::RustLibrary(_) => has_rust_library = true,
             Rule::RustBinary(_) => has_rust_binary = true,
@@ -289,6 +678,13 @@ pub fn gen_buck_content(rules: &[Rule]) -> String {
     // Build load statements based on which rule types are present
     let mut loads: Vec<Rule> = vec![];
 
+    if has_git_fetch {
+        loads.push(Rule::Load(Load {
+            bzl: format!("//{RUST_GIT_REPOS_ROOT}:git_fetch.bzl"),
+            items: Set::from(["git_fetch".to_owned()]),
+        }));
+    }
+
     if has_cargo_manifest {
         loads.push(Rule::Load(Load {
             bzl: "@buckal//:cargo_manifest.bzl".to_owned(),
@@ -340,8 +736,9 @@ pub fn gen_buck_content(rules: &[Rule]) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::RepoConfig;
-    use cargo_metadata::{TargetKind, camino::Utf8PathBuf};
+    use crate::config::{RepoConfig, WarningsPolicy};
+    use crate::context::mock_context;
+    use cargo_metadata::TargetKind;
     use std::collections::HashMap;
 
     fn mock_target(name: &str, kind: TargetKind) -> cargo_metadata::Target {
@@ -362,7 +759,7 @@ mod tests {
         serde_json::from_value(serde_json::json!({
             "name": name,
             "version": "0.1.0",
-            "id": format!("{} 0.1.0 (registry+...)", name),
+            "id": format!("{}@0.1.0", name),
             "license": null,
             "license_file": null,
             "description": null,
@@ -388,6 +785,25 @@ mod tests {
         .unwrap()
     }
 
+    #[test]
+    fn test_gen_buck_content_git_fetch() {
+        let content = gen_buck_content(&[Rule::GitFetch(crate::buck::GitFetch {
+            name: "vendor".to_owned(),
+            repo: "https://github.com/tokio-rs/tokio.git".to_owned(),
+            rev: "65b910d7571f36aa231958992e005a7a1c0838e9".to_owned(),
+            shallow: true,
+            submodules: true,
+            sub_targets: Set::from(["tokio-util".to_owned()]),
+            visibility: Set::from(["PUBLIC".to_owned()]),
+        })]);
+        assert!(
+            content
+                .contains("load(\"//third-party/rust/git/_repos:git_fetch.bzl\", \"git_fetch\")")
+        );
+        assert!(content.contains("sub_targets = [\"tokio-util\"]"));
+        assert!(content.contains("shallow = True"));
+    }
+
     #[test]
     fn test_buckify_root_node_name_collision() {
         let lib = mock_target("foo", TargetKind::Lib);
@@ -407,15 +823,12 @@ mod tests {
 
         let ctx = BuckalContext {
             packages_map,
-            nodes_map: HashMap::new(),
             root: Some(pkg.clone()),
             repo_config: RepoConfig {
                 ignore_tests: false,
                 ..RepoConfig::default()
             },
-            checksums_map: HashMap::new(),
-            workspace_root: Utf8PathBuf::from("/tmp"),
-            no_merge: false,
+            ..mock_context()
         };
 
         let rules = buckify_root_node(&node, &ctx);
@@ -432,6 +845,48 @@ mod tests {
         assert_eq!(lib_rule.unwrap().name, "foo-lib");
     }
 
+    #[test]
+    fn test_buckify_root_node_warnings_policy() {
+        let lib = mock_target("foo", TargetKind::Lib);
+        let bin = mock_target("foo-cli", TargetKind::Bin);
+        let pkg = mock_package("foo", vec![lib, bin]);
+
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": pkg.id.clone(),
+            "deps": [],
+            "dependencies": [],
+            "features": []
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map: HashMap::from([(pkg.id.clone(), pkg.clone())]),
+            root: Some(pkg.clone()),
+            repo_config: RepoConfig {
+                warnings: WarningsPolicy::Deny,
+                ..RepoConfig::default()
+            },
+            ..mock_context()
+        };
+
+        let rules = buckify_root_node(&node, &ctx);
+
+        let rustc_flags: Vec<_> = rules
+            .iter()
+            .filter_map(|r| match r {
+                Rule::RustLibrary(l) => Some(&l.rustc_flags),
+                Rule::RustBinary(b) => Some(&b.rustc_flags),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rustc_flags.len(), 2);
+        for flags in rustc_flags {
+            assert!(flags.contains("-Dwarnings"));
+            // Workspace members are not capped like third-party crates
+            assert!(!flags.iter().any(|f| f.starts_with("--cap-lints")));
+        }
+    }
+
     #[test]
     fn test_buckify_root_node_test_deps_lib_alias() {
         let lib = mock_target("foo", TargetKind::Lib);
@@ -453,15 +908,12 @@ mod tests {
 
         let ctx = BuckalContext {
             packages_map,
-            nodes_map: HashMap::new(),
             root: Some(pkg.clone()),
             repo_config: RepoConfig {
                 ignore_tests: false,
                 ..RepoConfig::default()
             },
-            checksums_map: HashMap::new(),
-            workspace_root: Utf8PathBuf::from("/tmp"),
-            no_merge: false,
+            ..mock_context()
         };
 
         let rules = buckify_root_node(&node, &ctx);
@@ -481,5 +933,281 @@ mod tests {
         assert!(test_rule.is_some());
         let test_rule = test_rule.unwrap();
         assert!(test_rule.deps.contains(":foo-lib"));
+
+        // Doctests of the library run through the library rule itself
+        let lib_rule = rules.iter().find_map(|r| match r {
+            Rule::RustLibrary(l) if l.name == "foo-lib" => Some(l),
+            _ => None,
+        });
+        assert_eq!(lib_rule.unwrap().doctests, Some(true));
+    }
+
+    #[test]
+    fn test_buckify_root_node_examples() {
+        let lib = mock_target("foo", TargetKind::Lib);
+        let example = mock_target("demo", TargetKind::Example);
+        let mut lib_example = mock_target("plugin", TargetKind::Example);
+        lib_example.crate_types = vec![CrateType::Lib];
+
+        let pkg = mock_package("foo", vec![lib, example, lib_example]);
+
+        let mut packages_map = HashMap::new();
+        packages_map.insert(pkg.id.clone(), pkg.clone());
+
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": pkg.id.clone(),
+            "deps": [],
+            "dependencies": [],
+            "features": []
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map,
+            root: Some(pkg.clone()),
+            ..mock_context()
+        };
+
+        let rules = buckify_root_node(&node, &ctx);
+
+        let example_rule = rules.iter().find_map(|r| match r {
+            Rule::RustBinary(b) if b.name == "demo-example" => Some(b),
+            _ => None,
+        });
+        assert!(example_rule.is_some());
+        let example_rule = example_rule.unwrap();
+        assert_eq!(example_rule.crate_name, "demo");
+        assert!(example_rule.deps.contains(":foo"));
+
+        let lib_example_rule = rules.iter().find_map(|r| match r {
+            Rule::RustLibrary(l) if l.name == "plugin-example" => Some(l),
+            _ => None,
+        });
+        assert!(lib_example_rule.is_some());
+        assert!(lib_example_rule.unwrap().deps.contains(":foo"));
+    }
+
+    #[test]
+    fn test_buckify_root_node_required_features() {
+        let lib = mock_target("foo", TargetKind::Lib);
+        let mut cli = mock_target("cli", TargetKind::Bin);
+        cli.required_features = vec!["cli".to_owned()];
+        let mut tool = mock_target("tool", TargetKind::Bin);
+        tool.required_features = vec!["tool".to_owned()];
+        let mut admin = mock_target("admin", TargetKind::Bin);
+        admin.required_features = vec!["admin".to_owned()];
+        let mut pkg = mock_package("foo", vec![lib, cli, tool, admin]);
+        pkg.features.insert("admin".to_owned(), vec![]);
+
+        let mut packages_map = HashMap::new();
+        packages_map.insert(pkg.id.clone(), pkg.clone());
+
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": pkg.id.clone(),
+            "deps": [],
+            "dependencies": [],
+            "features": ["cli"]
+        }))
+        .unwrap();
+
+        let mut ctx = BuckalContext {
+            packages_map,
+            root: Some(pkg.clone()),
+            ..mock_context()
+        };
+
+        let find_bin = |rules: &[Rule], name: &str| {
+            rules.iter().find_map(|r| match r {
+                Rule::RustBinary(b) if b.name == name => Some(b.target_compatible_with.clone()),
+                _ => None,
+            })
+        };
+
+        // Targets whose required features cannot be enabled are skipped by default, the ones
+        // selectable at build time are kept for `patch_required_features`
+        let rules = buckify_root_node(&node, &ctx);
+        assert_eq!(find_bin(&rules, "cli"), Some(Set::new()));
+        assert_eq!(find_bin(&rules, "tool"), None);
+        assert_eq!(find_bin(&rules, "admin"), Some(Set::new()));
+
+        // ... or marked incompatible with every platform
+        ctx.repo_config.required_features = RequiredFeaturesPolicy::Incompatible;
+        let rules = buckify_root_node(&node, &ctx);
+        assert_eq!(find_bin(&rules, "cli"), Some(Set::new()));
+        assert_eq!(
+            find_bin(&rules, "tool"),
+            Some(Set::from(["config//:none".to_owned()]))
+        );
+        assert_eq!(find_bin(&rules, "admin"), Some(Set::new()));
+    }
+
+    #[test]
+    fn test_buckify_root_node_crate_types() {
+        let mut lib = mock_target("ffi", TargetKind::Lib);
+        lib.crate_types = vec![CrateType::RLib, CrateType::CDyLib, CrateType::StaticLib];
+        let mut native = mock_target("native", TargetKind::Lib);
+        native.crate_types = vec![CrateType::CDyLib];
+        let ffi = mock_package("ffi", vec![lib]);
+        let native_pkg = mock_package("native", vec![native]);
+
+        let mut packages_map = HashMap::new();
+        packages_map.insert(ffi.id.clone(), ffi.clone());
+        packages_map.insert(native_pkg.id.clone(), native_pkg.clone());
+
+        let ctx = BuckalContext {
+            packages_map,
+            root: Some(ffi.clone()),
+            ..mock_context()
+        };
+
+        let libraries = |pkg: &Package| {
+            let node: Node = serde_json::from_value(serde_json::json!({
+                "id": pkg.id.clone(),
+                "deps": [],
+                "dependencies": [],
+                "features": []
+            }))
+            .unwrap();
+            buckify_root_node(&node, &ctx)
+                .into_iter()
+                .filter_map(|r| match r {
+                    Rule::RustLibrary(l) => Some((l.name, l.crate_type, l.preferred_linkage)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The rlib stays the main rule, native library types get rules of their own
+        assert_eq!(
+            libraries(&ffi),
+            vec![
+                ("ffi".to_owned(), None, None),
+                (
+                    "ffi-cdylib".to_owned(),
+                    Some("cdylib".to_owned()),
+                    Some("shared".to_owned())
+                ),
+                (
+                    "ffi-staticlib".to_owned(),
+                    Some("staticlib".to_owned()),
+                    Some("static".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(
+            libraries(&native_pkg),
+            vec![(
+                "native".to_owned(),
+                Some("cdylib".to_owned()),
+                Some("shared".to_owned())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_buckify_root_node_bin_unittest() {
+        let lib = mock_target("foo", TargetKind::Lib);
+        let bin = mock_target("foo", TargetKind::Bin);
+        let mut untested = mock_target("tool", TargetKind::Bin);
+        untested.test = false;
+        let pkg = mock_package("foo", vec![lib, bin, untested]);
+
+        let mut packages_map = HashMap::new();
+        packages_map.insert(pkg.id.clone(), pkg.clone());
+
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": pkg.id.clone(),
+            "deps": [],
+            "dependencies": [],
+            "features": []
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map,
+            root: Some(pkg.clone()),
+            repo_config: RepoConfig {
+                ignore_tests: false,
+                ..RepoConfig::default()
+            },
+            ..mock_context()
+        };
+
+        let rules = buckify_root_node(&node, &ctx);
+
+        let bin_rule = rules.iter().find_map(|r| match r {
+            Rule::RustBinary(b) if b.name == "foo" => Some(b),
+            _ => None,
+        });
+        let test_rule = rules.iter().find_map(|r| match r {
+            Rule::RustTest(t) if t.name == "foo-unittest" => Some(t),
+            _ => None,
+        });
+        assert!(test_rule.is_some());
+        let test_rule = test_rule.unwrap();
+        assert_eq!(test_rule.crate_root, bin_rule.unwrap().crate_root);
+        assert!(test_rule.deps.contains(":foo-lib"));
+
+        assert!(
+            !rules
+                .iter()
+                .any(|r| matches!(r, Rule::RustTest(t) if t.name == "tool-unittest"))
+        );
+    }
+
+    #[test]
+    fn test_buckify_root_node_integration_test_env() {
+        let server = mock_target("server", TargetKind::Bin);
+        let client = mock_target("my-client", TargetKind::Bin);
+        let mut gated = mock_target("admin", TargetKind::Bin);
+        gated.required_features = vec!["admin".to_owned()];
+        let test = mock_target("cli", TargetKind::Test);
+        let pkg = mock_package("foo", vec![server, client, gated, test]);
+
+        let mut packages_map = HashMap::new();
+        packages_map.insert(pkg.id.clone(), pkg.clone());
+
+        let node: Node = serde_json::from_value(serde_json::json!({
+            "id": pkg.id.clone(),
+            "deps": [],
+            "dependencies": [],
+            "features": []
+        }))
+        .unwrap();
+
+        let ctx = BuckalContext {
+            packages_map,
+            root: Some(pkg.clone()),
+            repo_config: RepoConfig {
+                ignore_tests: false,
+                ..RepoConfig::default()
+            },
+            ..mock_context()
+        };
+
+        let rules = buckify_root_node(&node, &ctx);
+
+        let test_rule = rules.iter().find_map(|r| match r {
+            Rule::RustTest(t) if t.name == "cli" => Some(t),
+            _ => None,
+        });
+        assert!(test_rule.is_some());
+        assert_eq!(
+            test_rule.unwrap().env,
+            Map::from([
+                (
+                    "CARGO_BIN_EXE_my-client".to_owned(),
+                    "$(location :my-client)".to_owned()
+                ),
+                (
+                    "CARGO_BIN_EXE_server".to_owned(),
+                    "$(location :server)".to_owned()
+                ),
+                (
+                    "CARGO_TARGET_TMPDIR".to_owned(),
+                    "buck-out/cargo-tmp".to_owned()
+                ),
+            ])
+        );
     }
 }
@@ -0,0 +1,261 @@
+use starlark_syntax::codemap::{Pos, Span, Spanned};
+use starlark_syntax::syntax::ast::{
+    ArgumentP, AstExpr, AstLiteral, AstNoPayload, AstStmt, CallArgsP, ExprP, IdentP, Stmt,
+};
+use starlark_syntax::syntax::module::AstModuleFields;
+use starlark_syntax::syntax::{AstModule, Dialect};
+
+use super::cross::{call_has_arg, insert_pos_before_closing_paren, needs_leading_comma};
+
+/// Create a dummy span for AST nodes (required by starlark_syntax but not used for our purpose)
+fn dummy_span() -> Span {
+    Span::new(Pos::new(0), Pos::new(0))
+}
+
+/// Wrap a value in a Spanned with a dummy span
+fn spanned<T>(node: T) -> Spanned<T> {
+    Spanned {
+        span: dummy_span(),
+        node,
+    }
+}
+
+/// Build a string literal AST node
+fn build_string_literal(s: &str) -> AstExpr {
+    spanned(ExprP::Literal(AstLiteral::String(spanned(s.to_owned()))))
+}
+
+/// Build a list of string literals
+pub(super) fn build_string_list(items: &[String]) -> AstExpr {
+    let list_items: Vec<AstExpr> = items.iter().map(|s| build_string_literal(s)).collect();
+    spanned(ExprP::List(list_items))
+}
+
+/// Build an empty list
+pub(super) fn build_empty_list() -> AstExpr {
+    spanned(ExprP::List(vec![]))
+}
+
+/// Build a select() call with a dictionary argument
+pub(super) fn build_select(entries: &[(&str, AstExpr)]) -> AstExpr {
+    let dict_entries: Vec<(AstExpr, AstExpr)> = entries
+        .iter()
+        .map(|(k, v)| (build_string_literal(k), v.clone()))
+        .collect();
+
+    let dict_expr = spanned(ExprP::Dict(dict_entries));
+
+    let select_ident = spanned(ExprP::Identifier(spanned(IdentP {
+        ident: "select".to_owned(),
+        payload: (),
+    })));
+
+    let args = CallArgsP {
+        args: vec![spanned(ArgumentP::Positional(dict_expr))],
+    };
+
+    spanned(ExprP::Call(Box::new(select_ident), args))
+}
+
+/// Pretty-print an AST expression with proper indentation
+pub(super) fn pretty_print_expr(expr: &AstExpr, out: &mut String, indent: usize) {
+    match &expr.node {
+        ExprP::Literal(AstLiteral::String(s)) => {
+            write_string_literal(out, &s.node);
+        }
+        ExprP::List(items) => {
+            if items.is_empty() {
+                out.push_str("[]");
+            } else {
+                out.push_str("[\n");
+                for item in items {
+                    write_indent(out, indent + 4);
+                    pretty_print_expr(item, out, indent + 4);
+                    out.push_str(",\n");
+                }
+                write_indent(out, indent);
+                out.push(']');
+            }
+        }
+        ExprP::Call(callee, args) => {
+            // Handle select() calls specially
+            if let ExprP::Identifier(ident) = &callee.node
+                && ident.node.ident == "select"
+            {
+                out.push_str("select(");
+                if let Some(arg) = args.args.first()
+                    && let ArgumentP::Positional(dict_expr) = &arg.node
+                {
+                    pretty_print_dict(dict_expr, out, indent);
+                }
+                out.push(')');
+                return;
+            }
+            // Generic call handling (not used in our case)
+            out.push_str(&format!("{}", expr.node));
+        }
+        _ => {
+            out.push_str(&format!("{}", expr.node));
+        }
+    }
+}
+
+/// Pretty-print a dictionary expression
+fn pretty_print_dict(expr: &AstExpr, out: &mut String, indent: usize) {
+    if let ExprP::Dict(entries) = &expr.node {
+        out.push_str("{\n");
+        for (key, value) in entries {
+            write_indent(out, indent + 4);
+            pretty_print_expr(key, out, indent + 4);
+            out.push_str(": ");
+            pretty_print_expr(value, out, indent + 4);
+            out.push_str(",\n");
+        }
+        write_indent(out, indent);
+        out.push('}');
+    }
+}
+
+fn write_indent(out: &mut String, spaces: usize) {
+    for _ in 0..spaces {
+        out.push(' ');
+    }
+}
+
+fn write_string_literal(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Append `+ select_expr` to the list attribute `attr` of rules of kind `rule_kind`.
+///
+/// Only the rule named `target_name` is patched if given, otherwise every rule of that kind is. A
+/// rule without the attribute gets `attr = select_expr` instead.
+pub(super) fn append_select_to_attr(
+    buck_content: &str,
+    rule_kind: &str,
+    target_name: Option<&str>,
+    attr: &str,
+    select_expr: &str,
+) -> String {
+    // Parse the Starlark content into an AST
+    let ast = match AstModule::parse("BUCK", buck_content.to_owned(), &Dialect::Extended) {
+        Ok(ast) => ast,
+        Err(_) => return buck_content.to_owned(),
+    };
+
+    // Find the insertion points by walking the AST
+    let mut patches = Vec::new();
+    collect_attr_patches(ast.statement(), rule_kind, target_name, attr, &mut patches);
+
+    // Insert the select expression from the back so earlier positions stay valid
+    let mut out = buck_content.to_owned();
+    patches.sort_unstable_by_key(|patch| patch.pos());
+    for patch in patches.into_iter().rev() {
+        match patch {
+            AttrPatch::Append(pos) => {
+                out.insert_str(pos, &format!(" + {select_expr}"));
+            }
+            AttrPatch::Insert(pos) => {
+                let mut insert = String::new();
+                if needs_leading_comma(&out, pos) {
+                    insert.push(',');
+                }
+                if insert.is_empty() && out[..pos].ends_with('\n') {
+                    // The previous argument already ends its line
+                    insert.push_str(&format!("    {attr} = {select_expr},\n"));
+                } else {
+                    insert.push_str(&format!("\n    {attr} = {select_expr},\n"));
+                }
+                out.insert_str(pos, &insert);
+            }
+        }
+    }
+    out
+}
+
+enum AttrPatch {
+    /// Byte position just after the end of the existing list value
+    Append(usize),
+    /// Byte position just before the closing paren of a rule without the attribute
+    Insert(usize),
+}
+
+impl AttrPatch {
+    fn pos(&self) -> usize {
+        match self {
+            AttrPatch::Append(pos) | AttrPatch::Insert(pos) => *pos,
+        }
+    }
+}
+
+fn collect_attr_patches(
+    stmt: &AstStmt,
+    rule_kind: &str,
+    target_name: Option<&str>,
+    attr: &str,
+    out: &mut Vec<AttrPatch>,
+) {
+    match &stmt.node {
+        Stmt::Statements(stmts) => {
+            for s in stmts {
+                collect_attr_patches(s, rule_kind, target_name, attr, out);
+            }
+        }
+        Stmt::Expression(expr) => {
+            if let ExprP::Call(callee, args) = &expr.node
+                && let ExprP::Identifier(ident) = &callee.node
+                && ident.node.ident == rule_kind
+                && target_name.is_none_or(|name| call_name_matches(&args.args, name))
+            {
+                if let Some(pos) = find_list_attr_end(&args.args, attr) {
+                    out.push(AttrPatch::Append(pos));
+                } else if !call_has_arg(&args.args, attr)
+                    && let Some(pos) = insert_pos_before_closing_paren(expr.span)
+                {
+                    out.push(AttrPatch::Insert(pos));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn call_name_matches(args: &[Spanned<ArgumentP<AstNoPayload>>], target_name: &str) -> bool {
+    args.iter().any(|arg| {
+        if let ArgumentP::Named(name_spanned, value) = &arg.node
+            && name_spanned.node == "name"
+            && let ExprP::Literal(AstLiteral::String(s)) = &value.node
+        {
+            s.node == target_name
+        } else {
+            false
+        }
+    })
+}
+
+/// Get the byte position just after the value of the list attribute `attr`.
+///
+/// The value may already be a `select()` or a sum of lists and `select()`s.
+fn find_list_attr_end(args: &[Spanned<ArgumentP<AstNoPayload>>], attr: &str) -> Option<usize> {
+    args.iter().find_map(|arg| {
+        if let ArgumentP::Named(name_spanned, value) = &arg.node
+            && name_spanned.node == attr
+            && matches!(value.node, ExprP::List(_) | ExprP::Call(..) | ExprP::Op(..))
+        {
+            Some(value.span.end().get() as usize)
+        } else {
+            None
+        }
+    })
+}
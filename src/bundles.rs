@@ -193,7 +193,8 @@ impl BuckConfig {
     }
 }
 
-pub fn init_modifier(dest: &std::path::Path) -> Result<()> {
+/// Write the `PACKAGE` file setting up the modes, including one per custom Cargo profile.
+pub fn init_modifier(dest: &std::path::Path, custom_profiles: &[&str]) -> Result<()> {
     let mut package_file = std::fs::File::create(dest.join("PACKAGE"))?;
 
     writeln!(package_file, "# @generated by `cargo buckal`")?;
@@ -220,6 +221,15 @@ pub fn init_modifier(dest: &std::path::Path) -> Result<()> {
         package_file,
         "    \"release\": \"buckal//config/mode:release\","
     )?;
+    for profile in custom_profiles {
+        writeln!(
+            package_file,
+            "    \"{}\": \"//{}:{}\",",
+            profile,
+            crate::profiles::PROFILES_PACKAGE,
+            profile
+        )?;
+    }
     writeln!(package_file, "}}")?;
     writeln!(package_file, "set_cfg_constructor(aliases = ALIASES)")?;
     writeln!(package_file)?;
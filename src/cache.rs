@@ -4,17 +4,20 @@ use anyhow::{Error, Result, anyhow};
 use cargo_metadata::{Node, PackageId, camino::Utf8PathBuf};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::{UnwrapOrExit, get_cache_path};
+use cargo_util_schemas::core::PackageIdSpec;
+
+use crate::utils::{UnwrapOrExit, get_cache_path, get_fixups_path};
 
 // type Fingerprint = [u8; 32];
 
 /// CACHE_VERSION is incremented whenever the cache format or logic changes in a way that is not backward-compatible.
 ///
 /// Version 2: Added multi-platform support to the cache format.
+/// Version 3: Fingerprints cover the fixups file of the package.
 ///
 /// Migration strategy: There is no automatic migration; if a cache version mismatch is detected, the old cache is ignored and a new cache is created.
 /// This ensures correctness at the cost of recomputation.
-const CACHE_VERSION: u32 = 2;
+const CACHE_VERSION: u32 = 3;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Fingerprint([u8; 32]);
@@ -56,6 +59,22 @@ impl BuckalHash for Node {
     }
 }
 
+/// Get the fingerprint of a package: its resolve node and its fixups file if any, so that editing
+/// the fixups regenerates the BUCK file of the package.
+fn package_fingerprint(node: &Node) -> Fingerprint {
+    let fixups = PackageIdSpec::parse(&node.id.repr)
+        .ok()
+        .and_then(|spec| get_fixups_path(spec.name()).ok())
+        .and_then(|path| std::fs::read(path).ok());
+    let Some(fixups) = fixups else {
+        return node.fingerprint();
+    };
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&node.fingerprint().0);
+    hasher.update(&fixups);
+    Fingerprint(hasher.finalize().into())
+}
+
 pub trait PackageIdExt {
     /// ($WORKSPACE) → workspace_root
     fn resolve(&self, workspace_root: &Utf8PathBuf) -> Self;
@@ -105,7 +124,7 @@ impl BuckalCache {
     pub fn new(resolve: &HashMap<PackageId, Node>, workspace_root: &Utf8PathBuf) -> Self {
         let fingerprints = resolve
             .iter()
-            .map(|(id, node)| (id.canonicalize(workspace_root), node.fingerprint()))
+            .map(|(id, node)| (id.canonicalize(workspace_root), package_fingerprint(node)))
             .collect();
         Self {
             fingerprints,
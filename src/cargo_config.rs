@@ -0,0 +1,371 @@
+use std::{collections::BTreeMap as Map, str::FromStr};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_platform::Platform;
+use serde::Deserialize;
+
+use crate::{
+    buckal_warn,
+    platform::{supported_targets, triples_from_platform},
+};
+
+/// Settings of the Cargo configuration files that affect how crates are compiled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CargoConfig {
+    /// `build.rustflags`
+    pub rustflags: Vec<String>,
+    /// `[target.<triple>]` and `[target.<cfg>]` tables
+    pub target: Map<String, TargetConfig>,
+    /// `[env]` variables, with relative paths resolved
+    pub env: Map<String, String>,
+    /// `[source.<name>]` tables
+    pub source: Map<String, SourceConfig>,
+    /// Index URLs of the `[registries.<name>]` tables
+    pub registries: Map<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetConfig {
+    pub rustflags: Vec<String>,
+    pub linker: Option<String>,
+}
+
+/// A `[source.<name>]` table, replacing a source or defining a replacement
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceConfig {
+    pub replace_with: Option<String>,
+    pub registry: Option<String>,
+    pub local_registry: Option<String>,
+    pub directory: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default)]
+    build: BuildTable,
+    #[serde(default)]
+    target: Map<String, TargetTable>,
+    #[serde(default)]
+    env: Map<String, EnvValue>,
+    #[serde(default)]
+    source: Map<String, SourceConfig>,
+    #[serde(default)]
+    registries: Map<String, RegistryTable>,
+}
+
+#[derive(Deserialize)]
+struct RegistryTable {
+    index: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct BuildTable {
+    rustflags: Option<StringList>,
+}
+
+#[derive(Deserialize, Default)]
+struct TargetTable {
+    rustflags: Option<StringList>,
+    linker: Option<String>,
+}
+
+/// A list given either as an array or as a space-separated string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringList {
+    String(String),
+    List(Vec<String>),
+}
+
+impl StringList {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            StringList::String(s) => s.split_whitespace().map(str::to_owned).collect(),
+            StringList::List(list) => list,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EnvValue {
+    Value(String),
+    Table {
+        value: String,
+        #[serde(default)]
+        relative: bool,
+    },
+}
+
+/// Load the Cargo configuration hierarchy applying to the workspace.
+///
+/// Like Cargo, the `.cargo/config.toml` files of the workspace root and all its ancestors are
+/// merged with `$CARGO_HOME/config.toml`, the deepest file taking precedence.
+/// See https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure
+pub fn load_cargo_config(workspace_root: &Utf8Path) -> Result<CargoConfig> {
+    let mut config = CargoConfig::default();
+    for path in config_files(workspace_root) {
+        let content =
+            std::fs::read_to_string(&path).with_context(|| format!("failed to read `{}`", path))?;
+        let file: ConfigFile =
+            toml::from_str(&content).with_context(|| format!("failed to parse `{}`", path))?;
+        // Relative paths are relative to the parent of the `.cargo` directory
+        let base = path.parent().and_then(Utf8Path::parent).unwrap_or(&path);
+        config.merge(file, base);
+    }
+    Ok(config)
+}
+
+/// Get the Cargo home directory, `$CARGO_HOME` or `~/.cargo` by default.
+pub fn cargo_home() -> Utf8PathBuf {
+    std::env::var("CARGO_HOME")
+        .map(Utf8PathBuf::from)
+        .unwrap_or_else(|_| {
+            Utf8PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_owned()))
+                .join(".cargo")
+        })
+}
+
+/// Get the configuration files applying to the workspace, from the lowest to the highest precedence.
+fn config_files(workspace_root: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let mut dirs: Vec<Utf8PathBuf> = workspace_root
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .collect();
+    let cargo_home = cargo_home();
+    if !dirs.contains(&cargo_home) {
+        dirs.push(cargo_home);
+    }
+
+    dirs.into_iter()
+        .rev()
+        .filter_map(|dir| {
+            // `.cargo/config` is the legacy name of `.cargo/config.toml`
+            ["config.toml", "config"]
+                .into_iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+        })
+        .collect()
+}
+
+impl CargoConfig {
+    /// Merge a configuration file of higher precedence, whose arrays are appended to ours.
+    fn merge(&mut self, file: ConfigFile, base: &Utf8Path) {
+        if let Some(rustflags) = file.build.rustflags {
+            self.rustflags.extend(rustflags.into_vec());
+        }
+        for (key, table) in file.target {
+            let target = self.target.entry(key).or_default();
+            if let Some(rustflags) = table.rustflags {
+                target.rustflags.extend(rustflags.into_vec());
+            }
+            if let Some(linker) = table.linker {
+                // Program names are looked up in `PATH`, only paths are relative
+                target.linker = Some(if linker.contains('/') {
+                    base.join(linker).to_string()
+                } else {
+                    linker
+                });
+            }
+        }
+        for (name, value) in file.env {
+            let value = match value {
+                EnvValue::Value(value) => value,
+                EnvValue::Table {
+                    value,
+                    relative: true,
+                } => base.join(value).to_string(),
+                EnvValue::Table { value, .. } => value,
+            };
+            self.env.insert(name, value);
+        }
+        for (name, table) in file.source {
+            let source = self.source.entry(name).or_default();
+            if table.replace_with.is_some() {
+                source.replace_with = table.replace_with;
+            }
+            if table.registry.is_some() {
+                source.registry = table.registry;
+            }
+            if let Some(path) = table.local_registry {
+                source.local_registry = Some(base.join(path).to_string());
+            }
+            if let Some(path) = table.directory {
+                source.directory = Some(base.join(path).to_string());
+            }
+        }
+        for (name, table) in file.registries {
+            if let Some(index) = table.index {
+                self.registries.insert(name, index);
+            }
+        }
+    }
+
+    /// Get the `[target]` tables applying to each supported target triple, the triple table first.
+    fn platform_targets(&self) -> Map<&'static str, Vec<&TargetConfig>> {
+        let mut tables: Vec<(Platform, &TargetConfig)> = self
+            .target
+            .iter()
+            .filter_map(|(key, target)| {
+                Platform::from_str(key)
+                    .inspect_err(|e| buckal_warn!("Ignoring `[target.{}]`: {}", key, e))
+                    .ok()
+                    .map(|platform| (platform, target))
+            })
+            .collect();
+        tables.sort_by_key(|(platform, _)| matches!(platform, Platform::Cfg(_)));
+
+        let mut targets: Map<&'static str, Vec<&TargetConfig>> = supported_targets()
+            .iter()
+            .map(|(_, triple)| (*triple, Vec::new()))
+            .collect();
+        for (platform, target) in tables {
+            for triple in triples_from_platform(&platform) {
+                targets.entry(triple).or_default().push(target);
+            }
+        }
+        targets
+    }
+
+    /// Get the `rustc` flags of each supported target triple.
+    ///
+    /// Like Cargo, `build.rustflags` is only used on platforms without any `target.*.rustflags`.
+    pub fn platform_rustflags(&self) -> Map<&'static str, Vec<String>> {
+        self.platform_targets()
+            .into_iter()
+            .map(|(triple, targets)| {
+                let flags: Vec<String> = targets
+                    .iter()
+                    .flat_map(|t| t.rustflags.iter().cloned())
+                    .collect();
+                if targets.iter().any(|t| !t.rustflags.is_empty()) {
+                    (triple, flags)
+                } else {
+                    (triple, self.rustflags.clone())
+                }
+            })
+            .collect()
+    }
+
+    /// Get the linker configured for each supported target triple.
+    pub fn platform_linkers(&self) -> Map<&'static str, String> {
+        self.platform_targets()
+            .into_iter()
+            .filter_map(|(triple, targets)| {
+                let linker = targets.iter().find_map(|t| t.linker.clone())?;
+                Some((triple, linker))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge_files(files: &[(&str, &str)]) -> CargoConfig {
+        let mut config = CargoConfig::default();
+        for (base, content) in files {
+            config.merge(toml::from_str(content).unwrap(), Utf8Path::new(base));
+        }
+        config
+    }
+
+    #[test]
+    fn test_merge_config_files() {
+        let config = merge_files(&[
+            (
+                "/home/user",
+                r#"
+                [build]
+                rustflags = "-Dwarnings --cfg home"
+
+                [env]
+                PROFILE_DIR = { value = "profiles", relative = true }
+                "#,
+            ),
+            (
+                "/work/repo",
+                r#"
+                [build]
+                rustflags = ["--cfg", "repo"]
+
+                [target.x86_64-unknown-linux-gnu]
+                linker = "tools/clang-wrapper"
+                rustflags = ["-Ctarget-cpu=native"]
+
+                [env]
+                PROFILE_DIR = "/opt/profiles"
+                GREETING = { value = "hello" }
+                "#,
+            ),
+        ]);
+
+        assert_eq!(
+            config.rustflags,
+            vec!["-Dwarnings", "--cfg", "home", "--cfg", "repo"]
+        );
+        assert_eq!(
+            config.target["x86_64-unknown-linux-gnu"],
+            TargetConfig {
+                rustflags: vec!["-Ctarget-cpu=native".to_owned()],
+                linker: Some("/work/repo/tools/clang-wrapper".to_owned()),
+            }
+        );
+        assert_eq!(
+            config.env,
+            Map::from([
+                ("GREETING".to_owned(), "hello".to_owned()),
+                ("PROFILE_DIR".to_owned(), "/opt/profiles".to_owned()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_platform_rustflags() {
+        let config = merge_files(&[(
+            "/work/repo",
+            r#"
+            [build]
+            rustflags = ["--cfg", "build"]
+
+            [target.'cfg(windows)']
+            rustflags = ["-Ctarget-feature=+crt-static"]
+            linker = "lld-link"
+            "#,
+        )]);
+
+        let rustflags = config.platform_rustflags();
+        assert_eq!(
+            rustflags["x86_64-pc-windows-msvc"],
+            vec!["-Ctarget-feature=+crt-static"]
+        );
+        assert_eq!(
+            rustflags["x86_64-unknown-linux-gnu"],
+            vec!["--cfg", "build"]
+        );
+        assert_eq!(
+            config.platform_linkers(),
+            Map::from([("x86_64-pc-windows-msvc", "lld-link".to_owned())])
+        );
+
+        // Triple tables only apply to their triple, not to every triple of the same OS
+        let config = merge_files(&[(
+            "/work/repo",
+            r#"
+            [target.x86_64-unknown-linux-gnu]
+            linker = "x86_64-linux-gnu-gcc"
+            "#,
+        )]);
+        assert_eq!(
+            config.platform_linkers(),
+            Map::from([(
+                "x86_64-unknown-linux-gnu",
+                "x86_64-linux-gnu-gcc".to_owned()
+            )])
+        );
+    }
+}
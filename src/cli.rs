@@ -35,6 +35,9 @@ pub enum BuckalSubCommands {
     /// Automatically remove unused dependencies
     Autoremove(crate::commands::autoremove::AutoremoveArgs),
 
+    /// Execute all benchmarks of a local package
+    Bench(Box<crate::commands::bench::BenchArgs>),
+
     /// Compile the current package
     Build(crate::commands::build::BuildArgs),
 
@@ -67,6 +70,9 @@ pub enum BuckalSubCommands {
 
     /// Update dependencies in a manifest file
     Update(crate::commands::update::UpdateArgs),
+
+    /// Verify the checksums of third-party crates against Cargo.lock
+    Verify(crate::commands::verify::VerifyArgs),
 }
 
 impl Cli {
@@ -81,6 +87,7 @@ impl Cli {
                     Some(subcommand) => match subcommand {
                         BuckalSubCommands::Add(args) => commands::add::execute(args),
                         BuckalSubCommands::Autoremove(args) => commands::autoremove::execute(args),
+                        BuckalSubCommands::Bench(args) => commands::bench::execute(args),
                         BuckalSubCommands::Build(args) => commands::build::execute(args),
                         BuckalSubCommands::Clean(args) => commands::clean::execute(args),
                         BuckalSubCommands::Init(args) => commands::init::execute(args),
@@ -92,6 +99,7 @@ impl Cli {
                         BuckalSubCommands::Remove(args) => commands::remove::execute(args),
                         BuckalSubCommands::Test(args) => commands::test::execute(args),
                         BuckalSubCommands::Update(args) => commands::update::execute(args),
+                        BuckalSubCommands::Verify(args) => commands::verify::execute(args),
                     },
                     None => {
                         // If no subcommand is provided, print help information
@@ -159,6 +167,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_test_doc_conflicts_with_target_selection() {
+        let cli = Cli::try_parse_from(["cargo", "buckal", "test", "--doc"])
+            .expect("failed to parse test args with --doc");
+
+        match cli.command {
+            Commands::Buckal(args) => match args.subcommands {
+                Some(BuckalSubCommands::Test(test_args)) => assert!(test_args.doc),
+                other => panic!("expected test subcommand, got {other:?}"),
+            },
+        }
+
+        assert!(Cli::try_parse_from(["cargo", "buckal", "test", "--doc", "--lib"]).is_err());
+        assert!(
+            Cli::try_parse_from(["cargo", "buckal", "test", "--doc", "--all-targets"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_bench_accepts_filter_and_args() {
+        let cli = Cli::try_parse_from([
+            "cargo",
+            "buckal",
+            "bench",
+            "--bench",
+            "throughput",
+            "parse",
+            "--",
+            "--save-baseline",
+            "main",
+        ])
+        .expect("failed to parse bench args");
+
+        match cli.command {
+            Commands::Buckal(args) => match args.subcommands {
+                Some(BuckalSubCommands::Bench(bench_args)) => {
+                    assert_eq!(bench_args.bench, vec!["throughput".to_string()]);
+                    assert_eq!(bench_args.bench_name.as_deref(), Some("parse"));
+                    assert_eq!(
+                        bench_args.args,
+                        vec!["--save-baseline".to_string(), "main".to_string()]
+                    );
+                }
+                other => panic!("expected bench subcommand, got {other:?}"),
+            },
+        }
+    }
+
     #[test]
     fn test_cli_build_rejects_target_and_target_platforms() {
         let result = Cli::try_parse_from([
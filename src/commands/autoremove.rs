@@ -1,13 +1,18 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
 use cargo_metadata::MetadataCommand;
 use clap::Parser;
 use walkdir::WalkDir;
 
 use crate::{
-    RUST_ROOT, buckal_log, buckal_note,
-    utils::{UnwrapOrExit, ensure_prerequisites, get_buck2_root},
+    RUST_FIXUPS_ROOT, RUST_GIT_REPOS_ROOT, RUST_ROOT, buckal_log, buckal_note,
+    utils::{
+        UnwrapOrExit, ensure_prerequisites, get_buck2_root, get_git_repo_path_relative,
+        get_vendor_path_relative,
+    },
 };
 
 #[derive(Parser, Debug)]
@@ -32,73 +37,133 @@ pub fn execute(args: &AutoremoveArgs) {
     } else {
         MetadataCommand::new().exec().unwrap()
     };
-    let used_packages = cargo_metadata
+    // Paths of the packages relative to the third-party directory
+    let relative_to_root = |path: &str| path[RUST_ROOT.len() + 1..].to_owned();
+    let mut used_packages = cargo_metadata
         .packages
-        .into_iter()
-        .map(|p| {
-            format!(
-                "{}/{}/{}",
-                extract_kind_str(&p.id.repr).unwrap_or_exit(),
-                p.name,
-                p.version
-            )
-        })
+        .iter()
+        .filter_map(|p| get_vendor_path_relative(&p.id).ok())
+        .map(|path| relative_to_root(&path))
         .collect::<BTreeSet<_>>();
 
+    // Git repositories shared by the crates still in use, and the rule fetching them
+    for source in cargo_metadata
+        .packages
+        .iter()
+        .filter_map(|p| p.source.as_ref())
+    {
+        if source.repr.starts_with("git+") {
+            let repo_path = get_git_repo_path_relative(&source.repr).unwrap_or_exit();
+            used_packages.insert(relative_to_root(&repo_path));
+            used_packages.insert(relative_to_root(&format!(
+                "{RUST_GIT_REPOS_ROOT}/git_fetch.bzl"
+            )));
+        }
+    }
+
     if args.dry_run {
         buckal_note!("The following packages would be removed:");
     }
 
     let third_party_dir = buck2_root.join(RUST_ROOT);
-    for entry in WalkDir::new(&third_party_dir).min_depth(3).max_depth(3) {
-        let entry_path = entry.as_ref().unwrap().path();
-        let entry_label = entry_path
-            .strip_prefix(&third_party_dir)
+    for entry_path in unused_entries(third_party_dir.as_std_path(), &used_packages) {
+        let entry_display = entry_path
+            .strip_prefix(&buck2_root)
             .ok()
             .unwrap()
             .to_string_lossy()
             .into_owned();
-
-        if !used_packages.contains(&entry_label) {
-            let entry_display = entry_path
-                .strip_prefix(&buck2_root)
-                .ok()
-                .unwrap()
-                .to_string_lossy()
-                .into_owned();
-            if args.dry_run {
-                println!("  {}", entry_display);
-            } else {
-                buckal_log!("Removing", format!("{}", entry_display));
-                std::fs::remove_dir_all(entry.as_ref().unwrap().path()).unwrap_or_exit();
-            }
+        if args.dry_run {
+            println!("  {}", entry_display);
+        } else {
+            buckal_log!("Removing", format!("{}", entry_display));
+            remove_entry(&entry_path).unwrap_or_exit();
         }
     }
 
     if !args.dry_run {
         for entry in WalkDir::new(&third_party_dir).min_depth(2).max_depth(2) {
-            let is_empty = entry
-                .as_ref()
-                .unwrap()
-                .path()
-                .read_dir()
-                .unwrap_or_exit()
-                .next()
-                .is_none();
+            let entry = entry.unwrap_or_exit();
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            let is_empty = entry.path().read_dir().unwrap_or_exit().next().is_none();
             if is_empty {
-                std::fs::remove_dir_all(entry.as_ref().unwrap().path()).unwrap_or_exit();
+                std::fs::remove_dir_all(entry.path()).unwrap_or_exit();
             }
         }
     }
 }
 
-/// Extract the source kind (registry or git) from a package ID string
-fn extract_kind_str(string: &str) -> Result<&str> {
-    let (kind, _) = string
-        .split_once('+')
-        .ok_or_else(|| anyhow::format_err!("invalid source `{}`", string))?;
-    match kind {
-        "registry" => Ok("crates"),
-        _ => Ok(kind),
+/// Get the paths of the entries of the third-party directory that are not used anymore, sorted.
+///
+/// Entries are the vendored packages and git repositories, and `git/_repos/git_fetch.bzl`.
+fn unused_entries(third_party_dir: &Path, used_packages: &BTreeSet<String>) -> Vec<PathBuf> {
+    let fixups_prefix = format!("{}/", &RUST_FIXUPS_ROOT[RUST_ROOT.len() + 1..]);
+    WalkDir::new(third_party_dir)
+        .min_depth(3)
+        .max_depth(3)
+        .sort_by_file_name()
+        .into_iter()
+        .map(|entry| entry.unwrap_or_exit().into_path())
+        .filter(|entry_path| {
+            let entry_label = entry_path
+                .strip_prefix(third_party_dir)
+                .ok()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            // Fixups are maintained by hand, even for crates no longer in use
+            !entry_label.starts_with(&fixups_prefix) && !used_packages.contains(&entry_label)
+        })
+        .collect()
+}
+
+/// Remove an unused entry, which is a file for the `git_fetch` rule and a directory otherwise.
+fn remove_entry(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let third_party_dir = dir.path();
+        for path in [
+            "crates/used/1.0.0/BUCK",
+            "crates/unused/1.0.0/BUCK",
+            "git/_repos/0123456789abcdef/BUCK",
+            "git/_repos/git_fetch.bzl",
+            "fixups/unused/fixups.toml",
+        ] {
+            let path = third_party_dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        // The last git dependency is gone, so its repository and `git_fetch.bzl` are unused
+        let used_packages = BTreeSet::from(["crates/used/1.0.0".to_owned()]);
+        let unused = unused_entries(third_party_dir, &used_packages);
+        assert_eq!(
+            unused,
+            vec![
+                third_party_dir.join("crates/unused/1.0.0"),
+                third_party_dir.join("git/_repos/0123456789abcdef"),
+                third_party_dir.join("git/_repos/git_fetch.bzl"),
+            ]
+        );
+        for entry_path in &unused {
+            remove_entry(entry_path).unwrap();
+            assert!(!entry_path.exists());
+        }
+        assert!(third_party_dir.join("crates/used/1.0.0/BUCK").exists());
+        assert!(third_party_dir.join("fixups/unused/fixups.toml").exists());
     }
 }
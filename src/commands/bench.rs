@@ -0,0 +1,267 @@
+use crate::{
+    TARGET_TMPDIR,
+    buck2::Buck2Command,
+    buckal_error, buckal_log,
+    buckify::get_bench_name,
+    commands::build::resolve_mode,
+    utils::{
+        UnwrapOrExit, check_buck2_package, ensure_prerequisites, get_buck2_root, get_target,
+        platform_exists, validate_target_triple,
+    },
+};
+use anyhow::{Context, Result, anyhow};
+use cargo_metadata::{Metadata, MetadataCommand, TargetKind, camino::Utf8Path};
+use clap::Parser;
+use std::collections::HashSet;
+use std::process::exit;
+
+#[derive(Parser, Debug)]
+pub struct BenchArgs {
+    /// Package to run benchmarks for
+    #[arg(short, long, value_name = "SPEC")]
+    pub package: Vec<String>,
+
+    /// Benchmark all packages in the workspace
+    #[arg(long)]
+    pub workspace: bool,
+
+    /// Exclude packages from the benchmark
+    #[arg(long, value_name = "SPEC")]
+    pub exclude: Vec<String>,
+
+    /// Benchmark only the specified bench target
+    #[arg(long, value_name = "NAME")]
+    pub bench: Vec<String>,
+
+    /// Benchmark all bench targets
+    #[arg(long)]
+    pub benches: bool,
+
+    /// Compile, but don't run benchmarks
+    #[arg(long)]
+    pub no_run: bool,
+
+    /// Run all benchmarks regardless of failure
+    #[arg(long)]
+    pub no_fail_fast: bool,
+
+    /// Build artifacts with the specified profile
+    #[arg(long, value_name = "PROFILE-NAME", default_value = "bench")]
+    pub profile: String,
+
+    /// Number of parallel jobs, defaults to # of CPUs
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Build for the target triple (e.g., x86_64-unknown-linux-gnu)
+    #[arg(long, value_name = "TRIPLE", conflicts_with = "target_platforms")]
+    pub target: Option<String>,
+
+    /// Build for the target platform (passed to buck2 --target-platforms)
+    #[arg(long, value_name = "PLATFORM", conflicts_with = "target")]
+    pub target_platforms: Option<String>,
+
+    /// If specified, only run benches containing this string in their names
+    #[arg(value_name = "BENCHNAME")]
+    pub bench_name: Option<String>,
+
+    /// Arguments for the bench binary
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+pub fn execute(args: &BenchArgs) {
+    ensure_prerequisites().unwrap_or_exit();
+    check_buck2_package().unwrap_or_exit();
+
+    let metadata = MetadataCommand::new()
+        .exec()
+        .context("Failed to fetch cargo metadata")
+        .unwrap_or_exit();
+
+    let buck2_root = get_buck2_root().unwrap_or_exit();
+
+    // Tests and benches expect `CARGO_TARGET_TMPDIR` to exist, like Cargo creates it
+    let tmpdir = buck2_root.join(TARGET_TMPDIR);
+    std::fs::create_dir_all(&tmpdir)
+        .with_context(|| format!("failed to create `{}`", tmpdir))
+        .unwrap_or_exit();
+
+    let targets = resolve_targets(args, &metadata, &buck2_root)
+        .unwrap_or_exit_ctx("failed to resolve targets");
+
+    if targets.is_empty() {
+        eprintln!("No bench targets found.");
+        return;
+    }
+
+    // Benchmarks build with the `bench` profile by default, like `cargo bench`
+    let mode = resolve_mode(false, Some(&args.profile), &metadata)
+        .unwrap_or_exit_ctx("failed to resolve the build profile");
+
+    let target_platforms = if let Some(triple) = &args.target {
+        // Validate the target triple and get the corresponding platform
+        match validate_target_triple(triple) {
+            Ok(platform) => Some(platform),
+            Err(e) => {
+                buckal_error!(e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(platform) = &args.target_platforms {
+        Some(platform.clone())
+    } else {
+        let platform = format!("//platforms:{}", get_target());
+        if platform_exists(&platform) {
+            Some(platform)
+        } else {
+            None
+        }
+    };
+
+    let mut failed = false;
+    for target in &targets {
+        let mut cmd = if args.no_run {
+            Buck2Command::build(target)
+        } else {
+            Buck2Command::run(target)
+        };
+        if let Some(mode) = &mode {
+            cmd = cmd.arg("-m").arg(mode);
+        }
+
+        if let Some(platform) = &target_platforms {
+            cmd = cmd.arg("--target-platforms").arg(platform);
+        }
+
+        if let Some(jobs) = args.jobs {
+            cmd = cmd.arg("-j").arg(jobs.to_string());
+        }
+
+        if !args.no_run {
+            cmd = cmd.arg("--");
+            for arg in bench_binary_args(args) {
+                cmd = cmd.arg(arg);
+            }
+        }
+
+        let status = cmd.status().unwrap_or_exit_ctx("failed to execute buck2");
+        if status.success() {
+            if args.no_run {
+                buckal_log!("Built", target);
+            }
+        } else if args.no_fail_fast {
+            failed = true;
+        } else {
+            exit(status.code().unwrap_or(1));
+        }
+    }
+
+    if failed {
+        exit(1);
+    }
+}
+
+/// Arguments passed to each bench binary, mirroring what `cargo bench` passes
+fn bench_binary_args(args: &BenchArgs) -> Vec<String> {
+    let mut raw_args = Vec::new();
+    if let Some(name) = &args.bench_name {
+        raw_args.push(name.clone());
+    }
+    raw_args.extend_from_slice(&args.args);
+    raw_args.push("--bench".to_owned());
+    raw_args
+}
+
+fn resolve_targets(
+    args: &BenchArgs,
+    metadata: &Metadata,
+    buck2_root: &Utf8Path,
+) -> Result<Vec<String>> {
+    let workspace_members: HashSet<_> = metadata.workspace_members.iter().collect();
+
+    let current_dir = std::env::current_dir()?;
+    let mut targets = Vec::new();
+    let mut matched_names = HashSet::new();
+
+    for pkg in &metadata.packages {
+        // Only look at workspace members
+        if !workspace_members.contains(&pkg.id) || args.exclude.contains(&pkg.name) {
+            continue;
+        }
+
+        let pkg_dir = pkg
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow!("Package {} manifest has no parent directory", pkg.name))?;
+
+        let selected = if !args.package.is_empty() {
+            args.package.contains(&pkg.name)
+        } else if args.workspace {
+            true
+        } else {
+            // Default to the packages under the current directory
+            pkg_dir.as_std_path().starts_with(&current_dir)
+        };
+        if !selected {
+            continue;
+        }
+
+        let relative = pkg_dir
+            .strip_prefix(buck2_root)
+            .map_err(|_| anyhow!("Package {} outside root", pkg.name))?
+            .as_str()
+            // Normalize path separators for Buck2 (always use forward slashes)
+            .replace('\\', "/");
+
+        for target in &pkg.targets {
+            if !target.kind.contains(&TargetKind::Bench) {
+                continue;
+            }
+            if !args.bench.is_empty() && !args.benches {
+                if !args.bench.contains(&target.name) {
+                    continue;
+                }
+                matched_names.insert(target.name.clone());
+            }
+            targets.push(format!("//{}:{}", relative, get_bench_name(&target.name)));
+        }
+    }
+
+    if !args.benches
+        && let Some(missing) = args.bench.iter().find(|b| !matched_names.contains(*b))
+    {
+        return Err(anyhow!("error: no bench target named `{}`", missing));
+    }
+
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_binary_args() {
+        let args = BenchArgs {
+            package: vec![],
+            workspace: false,
+            exclude: vec![],
+            bench: vec![],
+            benches: false,
+            no_run: false,
+            no_fail_fast: false,
+            profile: "bench".to_string(),
+            jobs: None,
+            target: None,
+            target_platforms: None,
+            bench_name: Some("parse".to_string()),
+            args: vec!["--save-baseline".to_string(), "main".to_string()],
+        };
+
+        assert_eq!(
+            bench_binary_args(&args),
+            vec!["parse", "--save-baseline", "main", "--bench"]
+        );
+    }
+}
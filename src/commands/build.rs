@@ -1,9 +1,15 @@
-use clap::Parser;
+use std::collections::BTreeSet;
+
+use anyhow::Context;
+use cargo_metadata::{Metadata, MetadataCommand, Package};
+use clap::{Args, Parser};
 use serde::Deserialize;
 
 use crate::{
+    FEATURES_CONFIG_SECTION,
     buck2::Buck2Command,
-    buckal_error, buckal_log,
+    buckal_error, buckal_log, buckal_warn,
+    profiles::{load_profiles, profile_mode},
     utils::{
         UnwrapOrExit, check_buck2_package, ensure_prerequisites, get_buck2_root, get_target,
         platform_exists, validate_target_triple,
@@ -13,9 +19,13 @@ use crate::{
 #[derive(Parser, Debug)]
 pub struct BuildArgs {
     /// Build optimized artifacts with the release profile
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "profile")]
     pub release: bool,
 
+    /// Build artifacts with the specified profile
+    #[arg(long, value_name = "PROFILE-NAME")]
+    pub profile: Option<String>,
+
     /// Use verbose output (`-vv` very verbose output)
     #[arg(short, action = clap::ArgAction::Count)]
     pub verbose: u8,
@@ -51,6 +61,105 @@ pub struct BuildArgs {
     /// Build for the target platform (passed to buck2 `--target-platforms`)
     #[arg(long, value_name = "PLATFORM", conflicts_with = "target")]
     pub target_platforms: Option<String>,
+
+    #[command(flatten)]
+    pub features: FeatureArgs,
+}
+
+/// Feature selection flags, mirroring Cargo's
+#[derive(Args, Debug, Default)]
+pub struct FeatureArgs {
+    /// Space or comma separated list of features to activate
+    #[arg(short = 'F', long, value_name = "FEATURES")]
+    pub features: Vec<String>,
+
+    /// Activate all available features
+    #[arg(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[arg(long)]
+    pub no_default_features: bool,
+}
+
+impl FeatureArgs {
+    /// Get the features requested with `--features`, with the package named by their
+    /// `<package>/` prefix if any
+    fn requested(&self) -> Vec<(Option<String>, String)> {
+        self.features
+            .iter()
+            .flat_map(|f| f.split([' ', ',']))
+            .filter(|f| !f.is_empty())
+            .map(|f| match f.split_once('/') {
+                Some((package, feature)) => (Some(package.to_owned()), feature.to_owned()),
+                None => (None, f.to_owned()),
+            })
+            .collect()
+    }
+
+    /// Get the features enabled for the package, on top of the ones resolved at migration time
+    pub fn enabled<F: AsRef<str>>(&self, package: &Package, resolved: &[F]) -> Vec<String> {
+        let mut enabled: Vec<String> = resolved.iter().map(|f| f.as_ref().to_owned()).collect();
+        if self.all_features {
+            enabled.extend(package.features.keys().cloned());
+        } else {
+            enabled.extend(
+                self.requested()
+                    .into_iter()
+                    .filter(|(scope, _)| scope.as_ref().is_none_or(|s| *s == *package.name))
+                    .map(|(_, feature)| feature),
+            );
+        }
+        enabled
+    }
+
+    /// Get the buckconfig values (`-c` options) selecting the features in the generated BUCK files
+    ///
+    /// Features are selected per package, so that `<package>/<feature>` only applies to the named
+    /// package.
+    pub fn config_values(&self, metadata: &Metadata) -> Vec<String> {
+        let packages = metadata.workspace_packages();
+        for (scope, feature) in self.requested() {
+            let declared = packages.iter().any(|p| {
+                scope.as_ref().is_none_or(|s| *s == *p.name) && p.features.contains_key(&feature)
+            });
+            if !declared {
+                match scope {
+                    Some(scope) => buckal_warn!(
+                        "Ignoring feature `{}/{}`: no workspace package `{}` declares it",
+                        scope,
+                        feature,
+                        scope
+                    ),
+                    None => buckal_warn!(
+                        "Ignoring feature `{}`: no workspace package declares it",
+                        feature
+                    ),
+                }
+            }
+        }
+
+        let mut values = Vec::new();
+        for package in packages {
+            let features: BTreeSet<String> = self
+                .enabled(package, &[] as &[&str])
+                .into_iter()
+                .filter(|f| f != "default" && package.features.contains_key(f))
+                .collect();
+            values.extend(
+                features
+                    .iter()
+                    .map(|f| format!("{}.{}.{}=true", FEATURES_CONFIG_SECTION, package.name, f)),
+            );
+            if self.no_default_features && !self.all_features {
+                values.push(format!(
+                    "{}.{}.default=false",
+                    FEATURES_CONFIG_SECTION, package.name
+                ));
+            }
+        }
+        values
+    }
 }
 
 impl BuildArgs {
@@ -124,6 +233,11 @@ pub fn execute(args: &BuildArgs) {
         get_available_targets(&relative_path)
     };
 
+    let metadata = MetadataCommand::new()
+        .exec()
+        .context("failed to fetch cargo metadata")
+        .unwrap_or_exit();
+
     if targets.is_empty() {
         buckal_error!("no targets found matching the specified criteria");
         std::process::exit(1);
@@ -149,11 +263,22 @@ pub fn execute(args: &BuildArgs) {
         }
     };
 
+    let config_values = args.features.config_values(&metadata);
+    let mode = resolve_mode(args.release, args.profile.as_deref(), &metadata)
+        .unwrap_or_exit_ctx("failed to resolve the build profile");
+
     // Execute build for each target
     for target in targets {
-        let mut buck2_cmd = Buck2Command::build(&target).verbosity(args.verbose);
-        if args.release {
-            buck2_cmd = buck2_cmd.arg("-m").arg("release");
+        // Targets whose `required-features` are not selected are incompatible, Buck2 reports
+        // them with the features they need and skips them like Cargo does
+        let mut buck2_cmd = Buck2Command::build(&target)
+            .verbosity(args.verbose)
+            .arg("--skip-incompatible-targets");
+        if let Some(mode) = &mode {
+            buck2_cmd = buck2_cmd.arg("-m").arg(mode);
+        }
+        for value in &config_values {
+            buck2_cmd = buck2_cmd.arg("-c").arg(value);
         }
         if let Some(platform) = &target_platforms {
             buck2_cmd = buck2_cmd.arg("--target-platforms").arg(platform);
@@ -179,6 +304,18 @@ pub fn execute(args: &BuildArgs) {
     }
 }
 
+/// Get the Buck2 mode selecting the profile requested with `--release` or `--profile`.
+pub fn resolve_mode(
+    release: bool,
+    profile: Option<&str>,
+    metadata: &Metadata,
+) -> anyhow::Result<Option<String>> {
+    match profile {
+        Some(name) => profile_mode(name, &load_profiles(&metadata.workspace_root)?),
+        None => Ok(release.then(|| "release".to_owned())),
+    }
+}
+
 /// Build specific targets based on target selection arguments
 fn build_specific_targets(args: &BuildArgs, relative_path: &str) -> Vec<String> {
     let mut targets = Vec::new();
@@ -383,7 +520,9 @@ fn get_example_targets(
     for pattern in example_patterns {
         for target in &example_targets {
             let target_name = extract_target_name(target, relative_path);
-            if pattern_matches(&target_name, pattern) {
+            // Generated example rules are named `<example>-example`, match against the Cargo name too
+            let example_name = target_name.strip_suffix("-example").unwrap_or(&target_name);
+            if pattern_matches(&target_name, pattern) || pattern_matches(example_name, pattern) {
                 matched_targets.push(target.clone());
             }
         }
@@ -483,11 +622,77 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn test_feature_args_requested() {
+        let args = FeatureArgs {
+            features: vec!["serde,fast".to_string(), "demo/extra std".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            args.requested(),
+            vec![
+                (None, "serde".to_owned()),
+                (None, "fast".to_owned()),
+                (Some("demo".to_owned()), "extra".to_owned()),
+                (None, "std".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_feature_args_config_values() {
+        let package = |name: &str, features: serde_json::Value| {
+            serde_json::json!({
+                "name": name,
+                "version": "0.1.0",
+                "id": format!("path+file:///ws/{name}#0.1.0"),
+                "source": null,
+                "dependencies": [],
+                "targets": [],
+                "features": features,
+                "manifest_path": format!("/ws/{name}/Cargo.toml"),
+                "edition": "2021"
+            })
+        };
+        let metadata: Metadata = serde_json::from_value(serde_json::json!({
+            "packages": [
+                package("foo", serde_json::json!({"default": ["bar"], "bar": [], "std": []})),
+                package("baz", serde_json::json!({"bar": [], "std": []})),
+            ],
+            "workspace_members": ["path+file:///ws/foo#0.1.0", "path+file:///ws/baz#0.1.0"],
+            "workspace_default_members": ["path+file:///ws/foo#0.1.0"],
+            "resolve": null,
+            "target_directory": "/ws/target",
+            "version": 1,
+            "workspace_root": "/ws",
+            "metadata": null
+        }))
+        .unwrap();
+
+        // `foo/bar` stays scoped to `foo`, unscoped features apply to every package declaring them
+        let args = FeatureArgs {
+            features: vec!["foo/bar,std".to_string()],
+            no_default_features: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            args.config_values(&metadata),
+            vec![
+                "cargo_features.foo.bar=true",
+                "cargo_features.foo.std=true",
+                "cargo_features.foo.default=false",
+                "cargo_features.baz.std=true",
+                "cargo_features.baz.default=false",
+            ]
+        );
+    }
+
     #[test]
     fn test_build_args_validation() {
         // Test valid combinations
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: true,
             bin: vec![],
@@ -497,11 +702,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.validate_target_selection().is_ok());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec!["myapp".to_string()],
@@ -511,12 +718,14 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.validate_target_selection().is_ok());
 
         // Test valid: only all-targets
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec![],
@@ -526,12 +735,14 @@ mod tests {
             all_targets: true,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.validate_target_selection().is_ok());
 
         // Test invalid combination: all-targets with other options
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: true,
             bin: vec![],
@@ -541,6 +752,7 @@ mod tests {
             all_targets: true,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.validate_target_selection().is_err());
     }
@@ -549,6 +761,7 @@ mod tests {
     fn test_has_target_selection() {
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec![],
@@ -558,11 +771,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(!args.has_target_selection());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: true,
             bin: vec![],
@@ -572,11 +787,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.has_target_selection());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec!["app".to_string()],
@@ -586,11 +803,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.has_target_selection());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec![],
@@ -600,6 +819,7 @@ mod tests {
             all_targets: true,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.has_target_selection());
     }
@@ -608,6 +828,7 @@ mod tests {
     fn test_has_other_target_selection() {
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec![],
@@ -617,11 +838,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(!args.has_other_target_selection());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: true,
             bin: vec![],
@@ -631,11 +854,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.has_other_target_selection());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec!["app".to_string()],
@@ -645,11 +870,13 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(args.has_other_target_selection());
 
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: false,
             bin: vec![],
@@ -659,6 +886,7 @@ mod tests {
             all_targets: true,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
         assert!(!args.has_other_target_selection());
     }
@@ -809,6 +1037,19 @@ mod tests {
         assert!(targets[0].contains("demo_example"));
     }
 
+    #[test]
+    fn test_example_targets_match_cargo_name() {
+        let available_targets = vec![
+            "//:foo".to_string(),
+            "//:hello-example".to_string(),
+            "//:hello_world-example".to_string(),
+        ];
+        let targets = get_example_targets(&available_targets, "", &["hello".to_string()], false);
+
+        // Should find only the rule generated for the `hello` example
+        assert_eq!(targets, vec!["//:hello-example".to_string()]);
+    }
+
     #[test]
     fn test_duplicate_removal() {
         let mut targets = vec![
@@ -865,6 +1106,7 @@ mod tests {
     fn test_mixed_target_selection() {
         let args = BuildArgs {
             release: false,
+            profile: None,
             verbose: 0,
             lib: true,
             bin: vec!["main*".to_string()],
@@ -874,6 +1116,7 @@ mod tests {
             all_targets: false,
             target: None,
             target_platforms: None,
+            features: FeatureArgs::default(),
         };
 
         assert!(args.has_target_selection());
@@ -99,7 +99,7 @@ pub fn execute(args: &InitArgs) {
         extract_buck2_assets(&cwd).unwrap_or_exit_ctx("failed to extract buck2 assets");
 
         // Init cfg modifiers
-        init_modifier(&cwd).unwrap_or_exit();
+        init_modifier(&cwd, &[]).unwrap_or_exit();
     } else {
         // Create a new buck2 cell
         let _buck =
@@ -11,6 +11,7 @@ use crate::{
     bundles::{fetch_buckal_cell, init_buckal_cell, init_modifier},
     cache::BuckalCache,
     context::BuckalContext,
+    profiles::{custom_profiles, write_profile_modes},
     utils::{UnwrapOrExit, append_buck_out_to_gitignore, ensure_prerequisites, get_buck2_root},
 };
 
@@ -35,6 +36,10 @@ pub struct MigrateArgs {
     /// Path to Cargo.toml
     #[arg(long, conflicts_with = "init")]
     pub manifest_path: Option<String>,
+
+    /// Unpack crate sources into `third-party` for offline builds, from the Cargo cache or the given `cargo vendor` directory
+    #[clap(long, value_name = "DIR", num_args = 0..=1)]
+    pub vendor: Option<Option<PathBuf>>,
 }
 
 pub fn execute(args: &MigrateArgs) {
@@ -101,7 +106,7 @@ pub fn execute(args: &MigrateArgs) {
             .unwrap_or_exit_ctx("failed to extract buck2 assets");
 
         // Init cfg modifiers
-        init_modifier(buck2_root.as_std_path()).unwrap_or_exit();
+        init_modifier(buck2_root.as_std_path(), &[]).unwrap_or_exit();
     }
 
     // Fetch latest bundles if requested
@@ -113,11 +118,25 @@ pub fn execute(args: &MigrateArgs) {
     // get cargo metadata and generate context
     let mut ctx = BuckalContext::new(args.manifest_path.clone());
     ctx.no_merge = !args.merge;
+    if let Some(vendor_dir) = &args.vendor {
+        ctx.repo_config.vendor = true;
+        if let Some(vendor_dir) = vendor_dir {
+            let vendor_dir = std::fs::canonicalize(vendor_dir).unwrap_or_exit_ctx(format!(
+                "failed to resolve vendor directory `{}`",
+                vendor_dir.display()
+            ));
+            ctx.repo_config.vendor_dir = Some(vendor_dir.to_string_lossy().into_owned());
+        }
+    }
+
+    // Generate the modes of the custom profiles
+    flush_profile_modes(&ctx);
 
     // Process the root node
     flush_root(&ctx);
     // Process dep nodes
-    let last_cache = if args.no_cache || BuckalCache::load().is_err() {
+    // Switching to vendored sources regenerates every package
+    let last_cache = if args.no_cache || args.vendor.is_some() || BuckalCache::load().is_err() {
         BuckalCache::new_empty()
     } else {
         BuckalCache::load().unwrap_or_exit_ctx("failed to load existing cache")
@@ -131,3 +150,18 @@ pub fn execute(args: &MigrateArgs) {
     // Flush the new cache
     new_cache.save();
 }
+
+/// Generate the constraints of the custom profiles and their aliases in the root `PACKAGE` file.
+fn flush_profile_modes(ctx: &BuckalContext) {
+    let buck2_root = get_buck2_root().unwrap_or_exit_ctx("failed to get Buck2 project root");
+    write_profile_modes(&buck2_root, &ctx.profiles)
+        .unwrap_or_exit_ctx("failed to generate the modes of the custom profiles");
+
+    // Leave `PACKAGE` files not generated by buckal alone
+    let package_file = buck2_root.join("PACKAGE");
+    let generated = std::fs::read_to_string(&package_file)
+        .is_ok_and(|content| content.starts_with("# @generated by `cargo buckal`"));
+    if generated {
+        init_modifier(buck2_root.as_std_path(), &custom_profiles(&ctx.profiles)).unwrap_or_exit();
+    }
+}
@@ -1,5 +1,6 @@
 pub mod add;
 pub mod autoremove;
+pub mod bench;
 pub mod build;
 pub mod clean;
 pub mod init;
@@ -11,3 +12,4 @@ pub mod push;
 pub mod remove;
 pub mod test;
 pub mod update;
+pub mod verify;
@@ -109,7 +109,7 @@ pub fn execute(args: &NewArgs) {
         extract_buck2_assets(&repo_path).unwrap_or_exit_ctx("failed to extract buck2 assets");
 
         // Init cfg modifiers
-        init_modifier(&repo_path).unwrap_or_exit();
+        init_modifier(&repo_path, &[]).unwrap_or_exit();
     } else {
         // Create a new buck2 cell
         let _buck = std::fs::File::create(format!("{}/BUCK", args.path))
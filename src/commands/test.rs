@@ -1,13 +1,16 @@
 use crate::{
+    TARGET_TMPDIR,
     buck2::Buck2Command,
     buckal_error,
+    buckify::{get_bench_name, get_bin_test_name},
+    commands::build::{FeatureArgs, resolve_mode},
     utils::{
         UnwrapOrExit, check_buck2_package, ensure_prerequisites, get_buck2_root, get_target,
         platform_exists, validate_target_triple,
     },
 };
 use anyhow::{Context, Result, anyhow};
-use cargo_metadata::MetadataCommand;
+use cargo_metadata::{MetadataCommand, TargetKind};
 use clap::Parser;
 use std::collections::HashSet;
 use std::process::exit;
@@ -58,6 +61,13 @@ pub struct TestArgs {
     #[arg(long)]
     pub tests: bool,
 
+    /// Test only this library's documentation
+    #[arg(
+        long,
+        conflicts_with_all = ["all_targets", "lib", "bin", "bins", "example", "examples", "test", "tests"]
+    )]
+    pub doc: bool,
+
     /// Compile, but don't run tests
     #[arg(long)]
     pub no_run: bool,
@@ -79,9 +89,16 @@ pub struct TestArgs {
     pub target_platforms: Option<String>,
 
     /// Build artifacts in release mode, with optimizations
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "profile")]
     pub release: bool,
 
+    /// Build artifacts with the specified profile
+    #[arg(long, value_name = "PROFILE-NAME")]
+    pub profile: Option<String>,
+
+    #[command(flatten)]
+    pub features: FeatureArgs,
+
     /// If specified, only run tests containing this string in their names
     #[arg(value_name = "TESTNAME")]
     pub test_name: Option<String>,
@@ -102,6 +119,12 @@ pub fn execute(args: &TestArgs) {
 
     let buck2_root = get_buck2_root().unwrap_or_exit();
 
+    // Tests and benches expect `CARGO_TARGET_TMPDIR` to exist, like Cargo creates it
+    let tmpdir = buck2_root.join(TARGET_TMPDIR);
+    std::fs::create_dir_all(&tmpdir)
+        .with_context(|| format!("failed to create `{}`", tmpdir))
+        .unwrap_or_exit();
+
     let (targets, _is_specific_target) = resolve_targets(args, &metadata, &buck2_root)
         .unwrap_or_exit_ctx("failed to resolve targets");
 
@@ -168,8 +191,14 @@ pub fn execute(args: &TestArgs) {
         cmd = cmd.arg("--target-platforms").arg(platform);
     }
 
-    if args.release {
-        cmd = cmd.arg("-m").arg("release");
+    if let Some(mode) = resolve_mode(args.release, args.profile.as_deref(), &metadata)
+        .unwrap_or_exit_ctx("failed to resolve the build profile")
+    {
+        cmd = cmd.arg("-m").arg(mode);
+    }
+
+    for value in args.features.config_values(&metadata) {
+        cmd = cmd.arg("-c").arg(value);
     }
 
     if args.no_fail_fast {
@@ -207,6 +236,14 @@ fn resolve_targets(
     // Build a set of workspace members to filter out third-party dependencies efficiently
     let workspace_members: HashSet<_> = metadata.workspace_members.iter().collect();
 
+    if args.doc {
+        let doctests = resolve_doctest_targets(args, metadata, buck2_root)?;
+        if doctests.is_empty() {
+            return Err(anyhow!("error: no library targets with doctests found"));
+        }
+        return Ok((doctests, true));
+    }
+
     if let Some(name) = &args.test_name {
         if is_glob_pattern(name) {
             for pkg in &metadata.packages {
@@ -363,21 +400,39 @@ fn resolve_targets(
                 }
 
                 if target.kind.iter().any(|k| k.to_string() == "bin") {
-                    if args.bins {
-                        matches_kind = true;
-                    } else if !args.bin.is_empty() {
+                    let mut matches_bin = args.bins;
+                    if !args.bins && !args.bin.is_empty() {
                         for bin_arg in &args.bin {
                             if is_glob_pattern(bin_arg) {
                                 if glob_match(bin_arg, &target.name) {
-                                    matches_kind = true;
+                                    matches_bin = true;
                                     specific_found = true;
                                 }
                             } else if *bin_arg == target.name {
-                                matches_kind = true;
+                                matches_bin = true;
                                 specific_found = true;
                             }
                         }
                     }
+
+                    // Unit tests of a binary have a rule of their own next to the binary
+                    if matches_bin && target.test {
+                        let pkg_dir = pkg.manifest_path.parent().ok_or_else(|| {
+                            anyhow!("Package {} manifest has no parent directory", pkg.name)
+                        })?;
+                        let relative = pkg_dir
+                            .strip_prefix(buck2_root)
+                            .map_err(|_| anyhow!("Package {} outside root", pkg.name))?
+                            .as_str()
+                            // Normalize path separators for Buck2 (always use forward slashes)
+                            .replace('\\', "/");
+                        patterns.push(format!(
+                            "//{}:{}",
+                            relative,
+                            get_bin_test_name(&target.name)
+                        ));
+                        specific_found = true;
+                    }
                 }
 
                 if target.kind.iter().any(|k| k.to_string() == "example") {
@@ -476,10 +531,110 @@ fn resolve_targets(
     }
 
     patterns.retain(|p| !p.contains("third-party"));
+    // Bench targets built with the libtest harness are `rust_test` rules too, but like
+    // `cargo test` they are only run by `cargo buckal bench`
+    let bench_rules = bench_rule_labels(metadata, buck2_root);
+    patterns.retain(|p| !bench_rules.iter().any(|label| p.ends_with(label.as_str())));
+
+    // Like `cargo test`, doctests run too unless the kinds of targets to test are selected
+    if args.all_targets || !(has_kind_selection || args.tests) {
+        patterns.extend(resolve_doctest_targets(args, metadata, buck2_root)?);
+    }
 
     Ok((patterns, false))
 }
 
+/// Get the labels (without cell) of the rules emitted for the bench targets of the workspace.
+fn bench_rule_labels(
+    metadata: &cargo_metadata::Metadata,
+    buck2_root: &cargo_metadata::camino::Utf8Path,
+) -> HashSet<String> {
+    metadata
+        .workspace_packages()
+        .into_iter()
+        .filter_map(|pkg| {
+            let relative = pkg.manifest_path.parent()?.strip_prefix(buck2_root).ok()?;
+            // Normalize path separators for Buck2 (always use forward slashes)
+            let relative = relative.as_str().replace('\\', "/");
+            Some(
+                pkg.targets
+                    .iter()
+                    .filter(|t| t.kind.contains(&TargetKind::Bench))
+                    .map(move |t| format!("//{}:{}", relative, get_bench_name(&t.name))),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+/// Resolve the library rules running doctests for the selected packages.
+///
+/// Doctests are not separate rules: `buck2 test` runs them on a `rust_library` with `doctests = True`.
+fn resolve_doctest_targets(
+    args: &TestArgs,
+    metadata: &cargo_metadata::Metadata,
+    buck2_root: &cargo_metadata::camino::Utf8Path,
+) -> Result<Vec<String>> {
+    let workspace_members: HashSet<_> = metadata.workspace_members.iter().collect();
+    let current_dir = std::env::current_dir()?;
+    let mut targets = Vec::new();
+
+    for pkg in &metadata.packages {
+        if !workspace_members.contains(&pkg.id) || args.exclude.contains(&pkg.name) {
+            continue;
+        }
+
+        let pkg_dir = pkg
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow!("Package {} manifest has no parent directory", pkg.name))?;
+
+        let selected = if !args.package.is_empty() {
+            args.package.contains(&pkg.name)
+        } else if args.workspace {
+            true
+        } else {
+            pkg_dir.as_std_path().starts_with(&current_dir)
+        };
+        if !selected {
+            continue;
+        }
+
+        let Some(lib_target) = pkg.targets.iter().find(|t| {
+            t.kind.iter().any(|k| {
+                let s = k.to_string();
+                s == "lib" || s == "rlib" || s == "proc-macro"
+            })
+        }) else {
+            continue;
+        };
+        if !lib_target.doctest {
+            continue;
+        }
+
+        let relative = pkg_dir
+            .strip_prefix(buck2_root)
+            .map_err(|_| anyhow!("Package {} outside root", pkg.name))?
+            .as_str()
+            // Normalize path separators for Buck2 (always use forward slashes)
+            .replace('\\', "/");
+
+        // The library rule is suffixed when a binary shares its name
+        let has_same_name_bin = pkg
+            .targets
+            .iter()
+            .any(|t| t.name == lib_target.name && t.kind.iter().any(|k| k.to_string() == "bin"));
+        let name = if has_same_name_bin {
+            format!("{}-lib", lib_target.name)
+        } else {
+            lib_target.name.to_owned()
+        };
+        targets.push(format!("//{}:{}", relative, name));
+    }
+
+    Ok(targets)
+}
+
 fn is_glob_pattern(s: &str) -> bool {
     s.contains('*') || s.contains('?')
 }
@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use cargo_metadata::{
+    MetadataCommand,
+    camino::{Utf8Path, Utf8PathBuf},
+};
+use clap::Parser;
+
+use crate::{
+    RUST_CRATES_ROOT,
+    buck::{Rule, parse_buck_file},
+    buckal_error, buckal_log, buckal_note,
+    context::load_checksums,
+    utils::{UnwrapOrExit, ensure_prerequisites, get_buck2_root},
+    vendor::{vendored_checksum, verify_cargo_vendor_sources},
+};
+
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    /// Path to Cargo.toml
+    #[arg(long)]
+    pub manifest_path: Option<String>,
+}
+
+pub fn execute(args: &VerifyArgs) {
+    ensure_prerequisites().unwrap_or_exit();
+
+    let buck2_root = get_buck2_root().unwrap_or_exit();
+    let mut metadata_command = MetadataCommand::new();
+    if let Some(manifest) = &args.manifest_path {
+        metadata_command.manifest_path(manifest);
+    }
+    let cargo_metadata = metadata_command
+        .no_deps()
+        .exec()
+        .unwrap_or_exit_ctx("failed to get cargo metadata");
+    let checksums = load_checksums(&cargo_metadata.workspace_root)
+        .unwrap_or_exit_ctx("failed to load checksums from Cargo.lock");
+
+    let crates_dir = buck2_root.join(RUST_CRATES_ROOT);
+    let (verified, problems) = verify_crates(&crates_dir, &checksums)
+        .unwrap_or_exit_ctx(format!("failed to read `{}`", crates_dir));
+
+    if problems.is_empty() {
+        buckal_log!("Verified", format!("{} crates", verified));
+        return;
+    }
+    for problem in &problems {
+        buckal_error!(problem);
+    }
+    buckal_note!("Run `cargo buckal migrate --no-cache` to regenerate the BUCK files.");
+    std::process::exit(1);
+}
+
+/// Check the `sha256` of every crate under `crates_dir` against the checksums of `Cargo.lock`,
+/// returning the number of crates verified and the problems found.
+///
+/// Crates downloaded by `http_archive` are checked through their rule, and vendored crates
+/// through the checksum recorded when they were unpacked and the hashes of their files. Registry
+/// packages of `Cargo.lock` without a directory are reported as well.
+fn verify_crates(
+    crates_dir: &Utf8Path,
+    checksums: &HashMap<String, String>,
+) -> Result<(usize, Vec<String>)> {
+    let mut verified = 0;
+    let mut problems = Vec::new();
+    let mut found = HashSet::new();
+    for package_dir in version_dirs(crates_dir)? {
+        let relative = package_dir.strip_prefix(crates_dir).unwrap();
+        let (Some(name), Some(version)) = (relative.parent(), relative.file_name()) else {
+            continue;
+        };
+        let key = format!("{}-{}", name, version);
+        found.insert(key.clone());
+        let Some(expected) = checksums.get(&key) else {
+            problems.push(format!(
+                "`{}` is not in Cargo.lock, the BUCK file is stale",
+                package_dir
+            ));
+            continue;
+        };
+
+        let buck_file = package_dir.join("BUCK");
+        let rules = match parse_buck_file(&buck_file) {
+            Ok(rules) => rules,
+            Err(e) => {
+                problems.push(format!("failed to parse `{}`: {}", buck_file, e));
+                continue;
+            }
+        };
+        let archive_checksum = rules.values().find_map(|rule| match rule {
+            Rule::HttpArchive(r) => Some(r.sha256.clone()),
+            _ => None,
+        });
+        let is_vendored = archive_checksum.is_none();
+        let actual = archive_checksum.or_else(|| vendored_checksum(&package_dir));
+
+        match actual {
+            Some(actual) if &actual == expected && is_vendored => {
+                match verify_cargo_vendor_sources(&package_dir) {
+                    Ok(()) => verified += 1,
+                    Err(e) => problems.push(format!(
+                        "vendored sources of `{} v{}` were modified: {}",
+                        name, version, e
+                    )),
+                }
+            }
+            Some(actual) if &actual == expected => verified += 1,
+            Some(actual) => problems.push(format!(
+                "checksum mismatch of `{} v{}` in `{}`: expected {}, found {}",
+                name, version, package_dir, expected, actual
+            )),
+            None => problems.push(format!(
+                "no checksum found for `{} v{}` in `{}`",
+                name, version, package_dir
+            )),
+        }
+    }
+
+    let mut missing: Vec<&String> = checksums.keys().filter(|k| !found.contains(*k)).collect();
+    missing.sort();
+    for key in missing {
+        problems.push(format!(
+            "`{}` of Cargo.lock has no directory in `{}`",
+            key, crates_dir
+        ));
+    }
+    Ok((verified, problems))
+}
+
+/// Get the `<name>/<version>` directories of the crates, sorted.
+fn version_dirs(crates_dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut dirs = Vec::new();
+    if !crates_dir.is_dir() {
+        return Ok(dirs);
+    }
+    for name_entry in crates_dir.read_dir_utf8()? {
+        let name_entry = name_entry?;
+        if !name_entry.file_type()?.is_dir() {
+            continue;
+        }
+        for version_entry in name_entry.path().read_dir_utf8()? {
+            let version_entry = version_entry?;
+            if version_entry.file_type()?.is_dir() {
+                dirs.push(version_entry.into_path());
+            }
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `.cargo-checksum.json` of vendored sources holding an empty `src/lib.rs`
+    fn vendored_checksums(package: &str) -> String {
+        // sha256 of an empty file
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        format!(
+            r#"{{"files": {{"src/lib.rs": "{}"}}, "package": "{}"}}"#,
+            empty, package
+        )
+    }
+
+    #[test]
+    fn test_verify_crates() {
+        let dir = tempfile::tempdir().unwrap();
+        let crates_dir = Utf8Path::from_path(dir.path()).unwrap();
+        let http_archive = |sha256: &str| {
+            format!(
+                r#"http_archive(
+    name = "vendor",
+    urls = ["https://static.crates.io/crates/demo/demo-1.0.0.crate"],
+    sha256 = "{}",
+    type = "tar.gz",
+    strip_prefix = "demo-1.0.0",
+)
+"#,
+                sha256
+            )
+        };
+        for (path, content) in [
+            ("ok/1.0.0/BUCK", http_archive("aaa")),
+            ("tampered/1.0.0/BUCK", http_archive("bad")),
+            ("stale/0.1.0/BUCK", http_archive("ccc")),
+            ("vendored/2.0.0/BUCK", String::new()),
+            ("vendored/2.0.0/src/lib.rs", String::new()),
+            (
+                "vendored/2.0.0/.cargo-checksum.json",
+                vendored_checksums("ddd"),
+            ),
+            ("modified/1.0.0/BUCK", String::new()),
+            ("modified/1.0.0/src/lib.rs", "tampered".to_owned()),
+            (
+                "modified/1.0.0/.cargo-checksum.json",
+                vendored_checksums("eee"),
+            ),
+            ("extra/1.0.0/BUCK", String::new()),
+            ("extra/1.0.0/src/lib.rs", String::new()),
+            ("extra/1.0.0/src/injected.rs", "fn injected() {}".to_owned()),
+            (
+                "extra/1.0.0/.cargo-checksum.json",
+                vendored_checksums("ggg"),
+            ),
+        ] {
+            let path = crates_dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let checksums = HashMap::from([
+            ("ok-1.0.0".to_owned(), "aaa".to_owned()),
+            ("tampered-1.0.0".to_owned(), "bbb".to_owned()),
+            ("vendored-2.0.0".to_owned(), "ddd".to_owned()),
+            ("modified-1.0.0".to_owned(), "eee".to_owned()),
+            ("missing-1.0.0".to_owned(), "fff".to_owned()),
+            ("extra-1.0.0".to_owned(), "ggg".to_owned()),
+        ]);
+
+        let (verified, problems) = verify_crates(crates_dir, &checksums).unwrap();
+        assert_eq!(verified, 2);
+        assert_eq!(problems.len(), 5);
+        assert!(problems[0].contains("`extra v1.0.0` were modified"));
+        assert!(problems[0].contains("unexpected file `src/injected.rs`"));
+        assert!(problems[1].contains("`modified v1.0.0` were modified"));
+        assert!(problems[2].contains("stale/0.1.0"));
+        assert!(problems[3].contains("expected bbb, found bad"));
+        assert!(problems[4].contains("`missing-1.0.0` of Cargo.lock has no directory"));
+    }
+}
@@ -166,6 +166,19 @@ pub struct RepoConfig {
     pub align_cells: bool,
     pub ignore_tests: bool,
     pub patch_fields: Set<String>,
+    pub required_features: RequiredFeaturesPolicy,
+    pub cap_lints: CapLints,
+    pub warnings: WarningsPolicy,
+    /// Extra download URL templates of each registry, tried before the registry itself
+    pub mirrors: Map<String, Vec<String>>,
+    /// Unpack registry crates into `third-party` instead of downloading them at build time
+    pub vendor: bool,
+    /// `cargo vendor` directory to take the crates from, relative to the workspace root
+    pub vendor_dir: Option<String>,
+    /// How git repositories of dependencies are fetched
+    pub git: GitConfig,
+    /// Extra target triples whose features are resolved, such as `wasm32-unknown-unknown`
+    pub feature_targets: Vec<String>,
 }
 
 impl Default for RepoConfig {
@@ -174,6 +187,88 @@ impl Default for RepoConfig {
             align_cells: false,
             ignore_tests: true,
             patch_fields: Set::new(),
+            required_features: RequiredFeaturesPolicy::default(),
+            cap_lints: CapLints::default(),
+            warnings: WarningsPolicy::default(),
+            mirrors: Map::new(),
+            vendor: false,
+            vendor_dir: None,
+            git: GitConfig::default(),
+            feature_targets: Vec::new(),
+        }
+    }
+}
+
+/// Options of the `git_fetch` rules shared by the crates of a git repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Fetch only the locked revision, which some servers refuse
+    pub shallow: bool,
+    /// Check out submodules, like Cargo does
+    pub submodules: bool,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            shallow: true,
+            submodules: true,
+        }
+    }
+}
+
+/// How to handle targets whose `required-features` are not enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequiredFeaturesPolicy {
+    /// Do not emit a rule for the target, like Cargo skips it
+    #[default]
+    Skip,
+    /// Emit the rule but mark it incompatible with every platform
+    Incompatible,
+}
+
+/// Lint level third-party crates are capped to, like Cargo does for non-path dependencies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapLints {
+    #[default]
+    Allow,
+    Warn,
+    /// Do not cap lints
+    None,
+}
+
+impl CapLints {
+    pub fn rustc_flag(self) -> Option<String> {
+        match self {
+            CapLints::Allow => Some("--cap-lints=allow".to_owned()),
+            CapLints::Warn => Some("--cap-lints=warn".to_owned()),
+            CapLints::None => None,
+        }
+    }
+}
+
+/// How warnings of first-party crates are reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningsPolicy {
+    /// Report warnings without failing the build
+    #[default]
+    Warn,
+    /// Turn warnings into errors
+    Deny,
+    /// Silence warnings
+    Allow,
+}
+
+impl WarningsPolicy {
+    pub fn rustc_flag(self) -> Option<String> {
+        match self {
+            WarningsPolicy::Warn => None,
+            WarningsPolicy::Deny => Some("-Dwarnings".to_owned()),
+            WarningsPolicy::Allow => Some("-Awarnings".to_owned()),
         }
     }
 }
@@ -1,14 +1,33 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    process::Command,
+};
 
-use cargo_metadata::{MetadataCommand, Node, Package, PackageId, camino::Utf8PathBuf};
-use cargo_util_schemas::lockfile::TomlLockfile;
+use anyhow::{Context, Result, bail};
 
-use crate::{config::RepoConfig, utils::UnwrapOrExit};
+use cargo_metadata::{
+    MetadataCommand, Node, Package, PackageId,
+    camino::{Utf8Path, Utf8PathBuf},
+};
+use cargo_util_schemas::{lockfile::TomlLockfile, manifest::TomlProfile};
+
+use crate::{
+    buckal_warn,
+    cargo_config::{CargoConfig, load_cargo_config},
+    config::RepoConfig,
+    platform::feature_targets,
+    profiles::load_profiles,
+    utils::UnwrapOrExit,
+};
 
 pub struct BuckalContext {
     /// The root package of the workspace, if any
     pub root: Option<Package>,
     pub nodes_map: HashMap<PackageId, Node>,
+    /// Features of each package resolved for every feature target, keyed by triple
+    pub platform_features: HashMap<PackageId, BTreeMap<String, BTreeSet<String>>>,
+    /// Features of packages built for both the target and the exec platform with different features
+    pub exec_features: HashMap<PackageId, ExecFeatures>,
     pub packages_map: HashMap<PackageId, Package>,
     pub checksums_map: HashMap<String, String>,
     pub workspace_root: Utf8PathBuf,
@@ -16,18 +35,69 @@ pub struct BuckalContext {
     pub no_merge: bool,
     /// Repository configuration
     pub repo_config: RepoConfig,
+    /// Profiles declared in the workspace manifest
+    pub profiles: BTreeMap<String, TomlProfile>,
+    /// Settings of the Cargo configuration files applying to the workspace
+    pub cargo_config: CargoConfig,
+}
+
+/// An empty context for tests, to be completed with struct update syntax.
+#[cfg(test)]
+pub fn mock_context() -> BuckalContext {
+    BuckalContext {
+        root: None,
+        nodes_map: HashMap::new(),
+        platform_features: HashMap::new(),
+        exec_features: HashMap::new(),
+        packages_map: HashMap::new(),
+        checksums_map: HashMap::new(),
+        workspace_root: Utf8PathBuf::from("/tmp"),
+        no_merge: false,
+        repo_config: RepoConfig::default(),
+        profiles: BTreeMap::new(),
+        cargo_config: CargoConfig::default(),
+    }
+}
+
+/// Load the checksums of the registry packages in the `Cargo.lock` of the workspace, keyed by
+/// `<name>-<version>`.
+pub fn load_checksums(workspace_root: &Utf8Path) -> Result<HashMap<String, String>> {
+    let lock_path = workspace_root.join("Cargo.lock");
+    let lock_content = std::fs::read_to_string(&lock_path)
+        .with_context(|| format!("failed to read `{}`", lock_path))?;
+    let lock_file: TomlLockfile = toml::from_str(&lock_content)
+        .with_context(|| format!("failed to parse `{}`", lock_path))?;
+    Ok(lock_file
+        .package
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|p| {
+            p.checksum
+                .map(|checksum| (format!("{}-{}", p.name, p.version), checksum))
+        })
+        .collect())
+}
+
+/// Features of a package resolved separately for the target and the exec (host) platform.
+///
+/// Cargo's feature resolver does not unify the features of build dependencies, proc-macros and
+/// their dependencies with those of the same packages built for the target.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecFeatures {
+    pub target: BTreeSet<String>,
+    pub exec: BTreeSet<String>,
 }
 
 impl BuckalContext {
     pub fn new(manifest_path: Option<String>) -> Self {
-        let cargo_metadata = if let Some(manifest) = manifest_path {
-            MetadataCommand::new()
-                .manifest_path(manifest)
-                .exec()
-                .unwrap()
-        } else {
-            MetadataCommand::new().exec().unwrap()
+        let metadata_command = || {
+            let mut cmd = MetadataCommand::new();
+            if let Some(manifest) = &manifest_path {
+                cmd.manifest_path(manifest);
+            }
+            cmd
         };
+        let cargo_metadata = metadata_command().exec().unwrap();
         let root = cargo_metadata.root_package().map(|p| p.to_owned());
         let packages_map = cargo_metadata
             .packages
@@ -41,30 +111,224 @@ impl BuckalContext {
             .into_iter()
             .map(|n| (n.id.to_owned(), n))
             .collect::<HashMap<_, _>>();
-        let lock_path = cargo_metadata.workspace_root.join("Cargo.lock");
-        let lock_content =
-            std::fs::read_to_string(&lock_path).unwrap_or_exit_ctx("failed to read Cargo.lock");
-        let lock_file: TomlLockfile =
-            toml::from_str(&lock_content).unwrap_or_exit_ctx("failed to parse Cargo.lock");
-        let checksums_map = lock_file
-            .package
-            .unwrap_or_default()
-            .into_iter()
-            .filter_map(|p| {
-                p.checksum
-                    .map(|checksum| (format!("{}-{}", p.name, p.version), checksum))
-            })
-            .collect::<HashMap<_, _>>();
+        let checksums_map = load_checksums(&cargo_metadata.workspace_root)
+            .unwrap_or_exit_ctx("failed to load checksums from Cargo.lock");
         let repo_config = RepoConfig::load();
 
+        // `cargo metadata` unifies features across all platforms, so resolve each platform separately
+        let mut platform_features: HashMap<PackageId, BTreeMap<String, BTreeSet<String>>> =
+            HashMap::new();
+        for triple in feature_targets(&repo_config.feature_targets) {
+            let resolve = metadata_command()
+                .other_options(vec!["--filter-platform".to_owned(), triple.to_owned()])
+                .exec()
+                .ok()
+                .and_then(|metadata| metadata.resolve);
+            let Some(resolve) = resolve else {
+                buckal_warn!(
+                    "Failed to resolve features for `{}`, its features are left out.",
+                    triple
+                );
+                continue;
+            };
+            for node in resolve.nodes {
+                platform_features.entry(node.id).or_default().insert(
+                    triple.to_owned(),
+                    node.features.iter().map(|f| f.to_string()).collect(),
+                );
+            }
+        }
+
+        // `cargo metadata` unifies host and target features as well, `cargo tree` does not
+        let exec_features = resolve_exec_features(manifest_path.as_deref(), &packages_map)
+            .unwrap_or_else(|e| {
+                buckal_warn!(
+                    "Failed to resolve exec features ({:#}), build dependencies will share the features of the target.",
+                    e
+                );
+                HashMap::new()
+            });
+
+        let profiles = load_profiles(&cargo_metadata.workspace_root).unwrap_or_else(|e| {
+            buckal_warn!(
+                "Failed to load profiles ({:#}), profile settings are ignored.",
+                e
+            );
+            BTreeMap::new()
+        });
+
+        let cargo_config = load_cargo_config(&cargo_metadata.workspace_root).unwrap_or_else(|e| {
+            buckal_warn!(
+                "Failed to load Cargo configuration ({:#}), its settings are ignored.",
+                e
+            );
+            CargoConfig::default()
+        });
+
         Self {
             root,
             nodes_map,
+            platform_features,
+            exec_features,
             packages_map,
             checksums_map,
             workspace_root: cargo_metadata.workspace_root.clone(),
             no_merge: false,
             repo_config,
+            profiles,
+            cargo_config,
         }
     }
 }
+
+/// Resolve the packages built with different features for the target and the exec platform.
+fn resolve_exec_features(
+    manifest_path: Option<&str>,
+    packages_map: &HashMap<PackageId, Package>,
+) -> Result<HashMap<PackageId, ExecFeatures>> {
+    let mut cmd = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()));
+    cmd.args([
+        "tree",
+        "--workspace",
+        "--edges",
+        "normal,build,dev",
+        "--target",
+        "all",
+        "--charset",
+        "ascii",
+        "--format",
+        "{p}|{f}",
+    ]);
+    if let Some(manifest) = manifest_path {
+        cmd.args(["--manifest-path", manifest]);
+    }
+    let output = cmd.output().context("failed to execute `cargo tree`")?;
+    if !output.status.success() {
+        bail!(
+            "`cargo tree` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // `cargo tree` identifies packages by name and version only
+    let mut ids: HashMap<(String, String), Option<&PackageId>> = HashMap::new();
+    for package in packages_map.values() {
+        ids.entry((package.name.to_string(), package.version.to_string()))
+            .and_modify(|id| *id = None)
+            .or_insert(Some(&package.id));
+    }
+
+    let tree = parse_cargo_tree(&String::from_utf8_lossy(&output.stdout));
+    Ok(tree
+        .into_iter()
+        .filter_map(|(key, (target, exec))| {
+            let (target, exec) = (target?, exec?);
+            let id = ids.get(&key).copied().flatten()?;
+            (target != exec).then(|| (id.clone(), ExecFeatures { target, exec }))
+        })
+        .collect())
+}
+
+type TreeFeatures = (Option<BTreeSet<String>>, Option<BTreeSet<String>>);
+
+/// Collect the features of every package in the output of `cargo tree --format "{p}|{f}"`,
+/// split into the features built for the target and for the exec platform.
+///
+/// Everything below a `[build-dependencies]` section or a proc-macro is built for the exec platform.
+fn parse_cargo_tree(output: &str) -> HashMap<(String, String), TreeFeatures> {
+    const INDENT: usize = 4;
+
+    let mut features: HashMap<(String, String), TreeFeatures> = HashMap::new();
+    // Whether the last package seen at each depth is built for the exec platform
+    let mut exec_stack: Vec<bool> = Vec::new();
+    // Whether the current dependency section at each depth holds build dependencies
+    let mut build_sections: Vec<bool> = Vec::new();
+
+    for line in output.lines() {
+        let Some(start) = line.find(|c: char| !matches!(c, '|' | '`' | '-' | ' ')) else {
+            continue;
+        };
+        let (prefix, entry) = line.split_at(start);
+        let depth = prefix.len() / INDENT;
+
+        if entry.starts_with('[') {
+            // Section headers are indented like the parent, their entries one level deeper
+            build_sections.resize(depth + 2, false);
+            build_sections[depth + 1] = entry == "[build-dependencies]";
+            continue;
+        }
+
+        let entry = entry.strip_suffix(" (*)").unwrap_or(entry);
+        let Some((package, package_features)) = entry.rsplit_once('|') else {
+            continue;
+        };
+        let mut parts = package.split(' ');
+        let (Some(name), Some(version)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+
+        let is_exec = package.ends_with(" (proc-macro)")
+            || (depth > 0 && exec_stack.get(depth - 1).copied().unwrap_or(false))
+            || build_sections.get(depth).copied().unwrap_or(false);
+        exec_stack.truncate(depth);
+        exec_stack.push(is_exec);
+        build_sections.truncate(depth + 1);
+
+        let package_features = package_features
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .map(str::to_owned);
+        let (target, exec) = features
+            .entry((name.to_owned(), version.trim_start_matches('v').to_owned()))
+            .or_default();
+        let slot = if is_exec { exec } else { target };
+        slot.get_or_insert_with(BTreeSet::new)
+            .extend(package_features);
+    }
+
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_cargo_tree() {
+        let output = indoc! {"
+            demo v0.1.0 (/tmp/demo)|
+            |-- serde v1.0.228|default,std
+            |   `-- serde_derive v1.0.228 (proc-macro)|default
+            |       `-- syn v2.0.104|full
+            |-- memchr v2.7.4|
+            [build-dependencies]
+            `-- cc v1.2.37|
+                |-- memchr v2.7.4|std
+                `-- shlex v1.3.0|default,std (*)
+        "};
+
+        let features = parse_cargo_tree(output);
+        let set = |features: &[&str]| {
+            Some(
+                features
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<BTreeSet<_>>(),
+            )
+        };
+        let get = |name: &str, version: &str| {
+            features
+                .get(&(name.to_owned(), version.to_owned()))
+                .cloned()
+                .unwrap()
+        };
+
+        assert_eq!(get("serde", "1.0.228"), (set(&["default", "std"]), None));
+        assert_eq!(get("serde_derive", "1.0.228"), (None, set(&["default"])));
+        assert_eq!(get("syn", "2.0.104"), (None, set(&["full"])));
+        assert_eq!(get("memchr", "2.7.4"), (set(&[]), set(&["std"])));
+        assert_eq!(get("cc", "1.2.37"), (None, set(&[])));
+        assert_eq!(get("shlex", "1.3.0"), (None, set(&["default", "std"])));
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::{HashMap, HashSet},
+    process::Command,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use cargo_metadata::{Package, camino::Utf8PathBuf};
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::{buckal_warn, cargo_config::CargoConfig, config::RepoConfig};
+
+/// Name of the crates.io registry in Cargo configuration
+pub const CRATES_IO_REGISTRY: &str = "crates-io";
+
+const CRATES_IO_INDEX: &str = "registry+https://github.com/rust-lang/crates.io-index";
+const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
+const CRATES_IO_DL: &str = "https://static.crates.io/crates/{crate}/{crate}-{version}.crate";
+
+/// Markers of a `dl` template, see https://doc.rust-lang.org/cargo/reference/registry-index.html#index-configuration
+const DL_MARKERS: &[&str] = &[
+    "{crate}",
+    "{version}",
+    "{prefix}",
+    "{lowerprefix}",
+    "{sha256-checksum}",
+];
+
+/// `dl` templates of the registry indices fetched so far, keyed by index URL
+static DL_CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+/// Local directory sources already reported as not downloadable
+static LOCAL_SOURCES_WARNED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+#[derive(Deserialize)]
+struct IndexConfig {
+    dl: String,
+}
+
+/// Get the download URLs of a registry package: the mirrors of its registry configured in
+/// `buckal.toml` first, in order, then the `dl` URL of the index it is actually fetched from.
+pub fn download_urls(
+    package: &Package,
+    checksum: &str,
+    cargo_config: &CargoConfig,
+    repo_config: &RepoConfig,
+) -> Result<Vec<String>> {
+    let source = package
+        .source
+        .as_ref()
+        .ok_or_else(|| anyhow!("package `{}` has no source", package.name))?
+        .repr
+        .as_str();
+    let registry = registry_name(source, cargo_config);
+    let index = replaced_index(source, registry.as_deref(), cargo_config)?;
+    let dl = dl_template(&index)?;
+
+    let mirrors = registry
+        .as_deref()
+        .and_then(|name| repo_config.mirrors.get(name))
+        .into_iter()
+        .flatten();
+    let urls = mirrors
+        .chain(std::iter::once(&dl))
+        .map(|template| {
+            expand_dl_template(
+                template,
+                &package.name,
+                &package.version.to_string(),
+                checksum,
+            )
+        })
+        .unique()
+        .collect();
+    Ok(urls)
+}
+
+/// Get the source ID of a registry index URL, as found in package IDs.
+fn index_source_id(index: &str) -> String {
+    if index.starts_with("sparse+") || index.starts_with("registry+") {
+        index.to_owned()
+    } else {
+        format!("registry+{}", index)
+    }
+}
+
+fn is_crates_io(source: &str) -> bool {
+    source == CRATES_IO_INDEX || source == CRATES_IO_SPARSE_INDEX
+}
+
+/// Get the name of the registry of a source, as used in `[source]` and `[registries]` tables.
+fn registry_name(source: &str, cargo_config: &CargoConfig) -> Option<String> {
+    if is_crates_io(source) {
+        return Some(CRATES_IO_REGISTRY.to_owned());
+    }
+    cargo_config
+        .registries
+        .iter()
+        .find(|(_, index)| index_source_id(index) == source)
+        .map(|(name, _)| name.clone())
+}
+
+/// Follow the `replace-with` chain of a registry to the source ID of the index actually used.
+///
+/// Replacements are looked up in the `[source]` tables, then in the `[registries]` tables. Local
+/// directories cannot be downloaded from, so the original registry is used instead of them.
+fn replaced_index(
+    source: &str,
+    registry: Option<&str>,
+    cargo_config: &CargoConfig,
+) -> Result<String> {
+    let Some(mut name) = registry else {
+        return Ok(source.to_owned());
+    };
+    let mut index = source.to_owned();
+    let mut visited = vec![name];
+    loop {
+        let config = cargo_config.source.get(name);
+        if let Some(registry) = config.and_then(|c| c.registry.as_deref()) {
+            index = index_source_id(registry);
+        } else if config.is_some_and(|c| c.local_registry.is_some() || c.directory.is_some()) {
+            let warned = LOCAL_SOURCES_WARNED.get_or_init(Default::default);
+            if warned.lock().unwrap().insert(name.to_owned()) {
+                buckal_warn!(
+                    "Source `{}` is a local directory, which cannot be downloaded from, falling back to `{}`.",
+                    name,
+                    source
+                );
+            }
+            return Ok(source.to_owned());
+        } else if visited.len() > 1
+            && let Some(registry) = cargo_config.registries.get(name)
+        {
+            index = index_source_id(registry);
+        }
+
+        let Some(replace_with) = config.and_then(|c| c.replace_with.as_deref()) else {
+            break;
+        };
+        if visited.contains(&replace_with) {
+            bail!(
+                "detected a cycle of `replace-with` sources with `{}`",
+                replace_with
+            );
+        }
+        visited.push(replace_with);
+        name = replace_with;
+    }
+    Ok(index)
+}
+
+/// Get the `dl` template of a registry index from its `config.json`.
+fn dl_template(index: &str) -> Result<String> {
+    if is_crates_io(index) {
+        return Ok(CRATES_IO_DL.to_owned());
+    }
+    let cache = DL_CACHE.get_or_init(Default::default);
+    if let Some(dl) = cache.lock().unwrap().get(index) {
+        return Ok(dl.clone());
+    }
+
+    let content = fetch_index_config(index)
+        .with_context(|| format!("failed to read the `config.json` of index `{}`", index))?;
+    let config: IndexConfig = serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse the `config.json` of index `{}`", index))?;
+    cache
+        .lock()
+        .unwrap()
+        .insert(index.to_owned(), config.dl.clone());
+    Ok(config.dl)
+}
+
+fn fetch_index_config(index: &str) -> Result<String> {
+    let (kind, url) = index
+        .split_once('+')
+        .ok_or_else(|| anyhow!("invalid registry source `{}`", index))?;
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(std::fs::read_to_string(
+            Utf8PathBuf::from(path).join("config.json"),
+        )?);
+    }
+    match kind {
+        "sparse" => {
+            let url = format!("{}/config.json", url.trim_end_matches('/'));
+            Ok(reqwest::blocking::get(&url)?.error_for_status()?.text()?)
+        }
+        _ => {
+            // Git indices only have a `config.json` at the root of their checkout
+            let checkout = std::env::temp_dir().join(format!(
+                "buckal-index-{}",
+                blake3::hash(url.as_bytes()).to_hex()
+            ));
+            let _ = std::fs::remove_dir_all(&checkout);
+            let status = Command::new("git")
+                .args(["clone", "--depth", "1", "--quiet", url])
+                .arg(&checkout)
+                .status()
+                .context("failed to execute git")?;
+            if !status.success() {
+                bail!("failed to clone `{}`", url);
+            }
+            let content = std::fs::read_to_string(checkout.join("config.json"));
+            let _ = std::fs::remove_dir_all(&checkout);
+            Ok(content?)
+        }
+    }
+}
+
+/// Get the directory prefix of a crate in a registry index.
+fn index_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_owned(),
+        2 => "2".to_owned(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Expand the markers of a `dl` template, which is a URL prefix if it has none.
+///
+/// A `file://` prefix is a directory of `.crate` files, laid out like a local registry.
+fn expand_dl_template(template: &str, name: &str, version: &str, checksum: &str) -> String {
+    if !DL_MARKERS.iter().any(|marker| template.contains(marker)) {
+        if template.starts_with("file://") {
+            return format!(
+                "{}/{}-{}.crate",
+                template.trim_end_matches('/'),
+                name,
+                version
+            );
+        }
+        return format!(
+            "{}/{}/{}/download",
+            template.trim_end_matches('/'),
+            name,
+            version
+        );
+    }
+    let prefix = index_prefix(name);
+    template
+        .replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{prefix}", &prefix)
+        .replace("{sha256-checksum}", checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cargo_config::SourceConfig;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_expand_dl_template() {
+        assert_eq!(
+            expand_dl_template(CRATES_IO_DL, "serde", "1.0.0", "abc"),
+            "https://static.crates.io/crates/serde/serde-1.0.0.crate"
+        );
+        assert_eq!(
+            expand_dl_template(
+                "https://crates.internal/api/v1/crates/",
+                "serde",
+                "1.0.0",
+                "abc"
+            ),
+            "https://crates.internal/api/v1/crates/serde/1.0.0/download"
+        );
+        assert_eq!(
+            expand_dl_template(
+                "https://mirror.example.com/{prefix}/{lowerprefix}/{crate}-{version}.crate?sha={sha256-checksum}",
+                "Inflector",
+                "0.11.4",
+                "abc"
+            ),
+            "https://mirror.example.com/In/fl/in/fl/Inflector-0.11.4.crate?sha=abc"
+        );
+        assert_eq!(
+            expand_dl_template("file:///mnt/crates/", "serde", "1.0.0", "abc"),
+            "file:///mnt/crates/serde-1.0.0.crate"
+        );
+        assert_eq!(index_prefix("a"), "1");
+        assert_eq!(index_prefix("ab"), "2");
+        assert_eq!(index_prefix("abc"), "3/a");
+    }
+
+    #[test]
+    fn test_replaced_index() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("config.json"),
+            r#"{"dl": "https://crates.internal/api/v1/crates", "api": "https://crates.internal"}"#,
+        )
+        .unwrap();
+        let local_index = format!("sparse+file://{}", dir.path().display());
+
+        let cargo_config = CargoConfig {
+            source: BTreeMap::from([
+                (
+                    CRATES_IO_REGISTRY.to_owned(),
+                    SourceConfig {
+                        replace_with: Some("internal".to_owned()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "internal".to_owned(),
+                    SourceConfig {
+                        registry: Some(local_index.clone()),
+                        ..Default::default()
+                    },
+                ),
+            ]),
+            registries: BTreeMap::from([(
+                "internal".to_owned(),
+                "https://git.internal/index".to_owned(),
+            )]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            registry_name(CRATES_IO_SPARSE_INDEX, &cargo_config).as_deref(),
+            Some(CRATES_IO_REGISTRY)
+        );
+        assert_eq!(
+            registry_name("registry+https://git.internal/index", &cargo_config).as_deref(),
+            Some("internal")
+        );
+
+        let index =
+            replaced_index(CRATES_IO_INDEX, Some(CRATES_IO_REGISTRY), &cargo_config).unwrap();
+        assert_eq!(index, local_index);
+        assert_eq!(
+            dl_template(&index).unwrap(),
+            "https://crates.internal/api/v1/crates"
+        );
+    }
+
+    #[test]
+    fn test_replaced_index_fallbacks() {
+        let cargo_config = CargoConfig {
+            source: BTreeMap::from([
+                (
+                    CRATES_IO_REGISTRY.to_owned(),
+                    SourceConfig {
+                        replace_with: Some("vendored-sources".to_owned()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "vendored-sources".to_owned(),
+                    SourceConfig {
+                        directory: Some("vendor".to_owned()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "internal".to_owned(),
+                    SourceConfig {
+                        replace_with: Some("mirror".to_owned()),
+                        ..Default::default()
+                    },
+                ),
+            ]),
+            registries: BTreeMap::from([
+                (
+                    "internal".to_owned(),
+                    "https://git.internal/index".to_owned(),
+                ),
+                (
+                    "mirror".to_owned(),
+                    "sparse+https://mirror.internal/index/".to_owned(),
+                ),
+            ]),
+            ..Default::default()
+        };
+
+        // A local directory falls back to the registry it replaces
+        assert_eq!(
+            replaced_index(CRATES_IO_INDEX, Some(CRATES_IO_REGISTRY), &cargo_config).unwrap(),
+            CRATES_IO_INDEX
+        );
+
+        // A replacement only declared in `[registries]` is followed
+        assert_eq!(
+            replaced_index(
+                "registry+https://git.internal/index",
+                Some("internal"),
+                &cargo_config
+            )
+            .unwrap(),
+            "sparse+https://mirror.internal/index/"
+        );
+    }
+}
@@ -4,13 +4,17 @@ mod buck2;
 mod buckify;
 mod bundles;
 mod cache;
+mod cargo_config;
 mod cli;
 mod commands;
 mod config;
 mod context;
+mod crate_index;
 mod platform;
+mod profiles;
 mod registry;
 mod utils;
+mod vendor;
 
 use std::sync::OnceLock;
 
@@ -19,6 +23,17 @@ use clap::Parser;
 pub const RUST_ROOT: &str = "third-party/rust";
 pub const RUST_CRATES_ROOT: &str = "third-party/rust/crates";
 pub const RUST_GIT_ROOT: &str = "third-party/rust/git";
+// shared `git_fetch` rules of the git repositories, one per repository and revision
+pub const RUST_GIT_REPOS_ROOT: &str = "third-party/rust/git/_repos";
+// sources of path dependencies outside the Buck2 project, such as `[patch]` forks
+pub const RUST_PATH_ROOT: &str = "third-party/rust/path";
+// per-crate fixups of third-party crates, maintained by hand
+pub const RUST_FIXUPS_ROOT: &str = "third-party/rust/fixups";
+// scratch directory of integration tests and benches (`CARGO_TARGET_TMPDIR`), relative to the
+// project root tests run from, so that generated BUCK files do not depend on the checkout
+pub const TARGET_TMPDIR: &str = "buck-out/cargo-tmp";
+// buckconfig section selecting the Cargo features of first-party packages (`-c cargo_features.<package>.<name>=true`)
+pub const FEATURES_CONFIG_SECTION: &str = "cargo_features";
 pub const BUCKAL_BUNDLES_REPO: &str = "buck2hub/buckal-bundles";
 // fallback commit hash used when fetching the latest from BUCKAL_BUNDLES_REPO fails
 pub const DEFAULT_BUNDLE_HASH: &str = "bb154eeec3fc42390eeb995ccb3b1f2893864fc8";
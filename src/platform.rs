@@ -45,6 +45,11 @@ static SUPPORTED_TARGETS: &[(Os, &str)] = &[
     (Os::Linux, "x86_64-unknown-linux-gnu"),
 ];
 
+/// Targets of the bundled `//platforms` package whose features can be resolved on top of
+/// [`SUPPORTED_TARGETS`] when `buckal.toml` lists them, as dependencies often enable features of
+/// their own for them (e.g. `getrandom/js` on `wasm32`).
+static EXTRA_FEATURE_TARGETS: &[&str] = &["wasm32-unknown-unknown"];
+
 /// Cache of `rustc --print=cfg --target <triple>` output for supported triples.
 static CFG_CACHE: OnceLock<HashMap<&'static str, Vec<Cfg>>> = OnceLock::new();
 
@@ -150,6 +155,36 @@ fn cfg_cache() -> &'static HashMap<&'static str, Vec<Cfg>> {
     })
 }
 
+/// Returns the supported target triples along with their OS.
+pub fn supported_targets() -> &'static [(Os, &'static str)] {
+    SUPPORTED_TARGETS
+}
+
+/// Returns the target triples for which the features of each package are resolved: the supported
+/// targets, and the configured extra targets known to the bundled `//platforms` package.
+pub fn feature_targets(extra: &[String]) -> Vec<&'static str> {
+    let extra = extra.iter().filter_map(|triple| {
+        let known = EXTRA_FEATURE_TARGETS.iter().find(|t| **t == triple);
+        if known.is_none() {
+            buckal_warn!(
+                "Ignoring feature target `{}`: `//platforms` has no setting for it.",
+                triple
+            );
+        }
+        known.copied()
+    });
+    SUPPORTED_TARGETS
+        .iter()
+        .map(|(_, triple)| *triple)
+        .chain(extra)
+        .collect()
+}
+
+/// Returns the `config_setting` of the bundled `//platforms` package matching the target triple.
+pub fn target_setting(triple: &str) -> String {
+    format!("//platforms:target-{}", triple)
+}
+
 pub fn buck_labels(oses: &BTreeSet<Os>) -> BTreeSet<String> {
     oses.iter().map(|os| os.buck_label().to_string()).collect()
 }
@@ -185,6 +220,21 @@ pub fn oses_from_platform(platform: &Platform) -> BTreeSet<Os> {
         .collect()
 }
 
+/// Returns the supported target triples that satisfy a Cargo [`Platform`].
+///
+/// Like [`oses_from_platform`], triples without cfg output from `rustc` are skipped.
+pub fn triples_from_platform(platform: &Platform) -> BTreeSet<&'static str> {
+    let cfgs = cfg_cache();
+    SUPPORTED_TARGETS
+        .iter()
+        .map(|(_, triple)| *triple)
+        .filter(|triple| {
+            cfgs.get(triple)
+                .is_some_and(|cfgs| platform.matches(triple, cfgs))
+        })
+        .collect()
+}
+
 fn cfg_is_target_only(cfg: &Cfg) -> bool {
     match cfg {
         Cfg::Name(name) => matches!(name.as_str(), "windows" | "unix"),
@@ -434,6 +484,24 @@ mod tests {
         assert_eq!(labels, expected);
     }
 
+    #[test]
+    fn test_feature_targets() {
+        let triples = feature_targets(&[]);
+        assert!(triples.contains(&"x86_64-unknown-linux-gnu"));
+        assert!(!triples.contains(&"wasm32-unknown-unknown"));
+
+        let triples = feature_targets(&[
+            "wasm32-unknown-unknown".to_owned(),
+            "riscv64gc-unknown-none-elf".to_owned(),
+        ]);
+        assert!(triples.contains(&"wasm32-unknown-unknown"));
+        assert!(!triples.contains(&"riscv64gc-unknown-none-elf"));
+        assert_eq!(
+            target_setting("wasm32-unknown-unknown"),
+            "//platforms:target-wasm32-unknown-unknown"
+        );
+    }
+
     #[test]
     fn test_supported_targets() {
         // Test that supported targets are defined and non-empty
@@ -0,0 +1,356 @@
+use std::{collections::BTreeMap as Map, io::Write};
+
+use anyhow::{Result, bail};
+use cargo_metadata::{Package, camino::Utf8Path};
+use cargo_util_schemas::manifest::{
+    ProfilePackageSpec, StringOrBool, TomlDebugInfo, TomlOptLevel, TomlProfile,
+};
+
+use crate::utils::load_manifest;
+
+/// Buck package holding the constraints of the custom profiles, relative to the Buck2 project root
+pub const PROFILES_PACKAGE: &str = "platforms/profiles";
+
+/// Load the profiles declared in the workspace manifest.
+pub fn load_profiles(workspace_root: &Utf8Path) -> Result<Map<String, TomlProfile>> {
+    Ok(load_manifest(&workspace_root.join("Cargo.toml"))?
+        .profile
+        .map(|profiles| {
+            profiles
+                .get_all()
+                .iter()
+                .map(|(name, profile)| (name.to_string(), profile.clone()))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Whether the profile is built with one of the `debug` and `release` modes.
+fn is_builtin(name: &str) -> bool {
+    matches!(name, "dev" | "release")
+}
+
+/// Get the names of the custom profiles, which need a Buck2 mode of their own.
+///
+/// `test` and `bench` build with the `debug` and `release` modes unless the manifest declares
+/// them, in which case they get a mode like custom profiles.
+pub fn custom_profiles(profiles: &Map<String, TomlProfile>) -> Vec<&str> {
+    profiles
+        .keys()
+        .map(String::as_str)
+        .filter(|name| !is_builtin(name))
+        .collect()
+}
+
+/// Get the Buck2 mode (`-m`) building with the given profile, `None` for the default `debug` mode.
+pub fn profile_mode(name: &str, profiles: &Map<String, TomlProfile>) -> Result<Option<String>> {
+    match name {
+        _ if !is_builtin(name) && profiles.contains_key(name) => Ok(Some(name.to_owned())),
+        "dev" | "test" => Ok(None),
+        "release" | "bench" => Ok(Some("release".to_owned())),
+        _ => bail!("profile `{}` is not defined", name),
+    }
+}
+
+/// Get the constraint value of the mode of the given profile, `None` for the default `debug` mode.
+pub fn profile_constraint(name: &str) -> Option<String> {
+    match name {
+        "dev" => None,
+        "release" => Some("buckal//config/mode:release".to_owned()),
+        _ => Some(format!("//{}:{}", PROFILES_PACKAGE, name)),
+    }
+}
+
+/// Cargo's defaults for the root profiles.
+fn builtin_defaults(name: &str) -> TomlProfile {
+    let release = name == "release";
+    TomlProfile {
+        opt_level: Some(TomlOptLevel(if release { "3" } else { "0" }.to_owned())),
+        debug: Some(if release {
+            TomlDebugInfo::None
+        } else {
+            TomlDebugInfo::Full
+        }),
+        debug_assertions: Some(!release),
+        overflow_checks: Some(!release),
+        codegen_units: Some(if release { 16 } else { 256 }),
+        ..Default::default()
+    }
+}
+
+/// Resolve the settings of a profile through its `inherits` chain.
+///
+/// The `debug` and `release` modes already implement Cargo's defaults, so the built-in profiles
+/// only keep their explicit settings. Custom profiles are resolved down to the defaults.
+pub fn resolve_profile(name: &str, profiles: &Map<String, TomlProfile>) -> Result<TomlProfile> {
+    let mut chain = vec![name];
+    let mut current = name;
+    while !matches!(current, "dev" | "release") {
+        current = match (
+            current,
+            profiles.get(current).and_then(|p| p.inherits.as_deref()),
+        ) {
+            (_, Some(parent)) => parent,
+            ("test", None) => "dev",
+            ("bench", None) => "release",
+            (_, None) => bail!("profile `{}` must specify the profile it inherits", current),
+        };
+        if chain.contains(&current) {
+            bail!(
+                "profile inheritance loop detected with profile `{}`",
+                current
+            );
+        }
+        chain.push(current);
+    }
+
+    let mut resolved = if is_builtin(name) {
+        TomlProfile::default()
+    } else {
+        builtin_defaults(current)
+    };
+    for profile in chain.iter().rev().filter_map(|p| profiles.get(*p)) {
+        resolved.merge(profile);
+    }
+    Ok(resolved)
+}
+
+/// Apply the `[profile.<name>.package.<spec>]` overrides matching the package.
+///
+/// `*` overrides only apply to packages outside of the workspace.
+pub fn package_profile(profile: &TomlProfile, package: &Package, is_member: bool) -> TomlProfile {
+    let mut resolved = TomlProfile {
+        package: None,
+        build_override: None,
+        ..profile.clone()
+    };
+    let Some(overrides) = &profile.package else {
+        return resolved;
+    };
+    if !is_member && let Some(all) = overrides.get(&ProfilePackageSpec::All) {
+        resolved.merge(all);
+    }
+    for (spec, package_override) in overrides {
+        if let ProfilePackageSpec::Spec(spec) = spec
+            && spec.name() == package.name.as_str()
+            && spec.version().is_none_or(|v| v == package.version)
+        {
+            resolved.merge(package_override);
+        }
+    }
+    resolved
+}
+
+/// Translate the profile settings into `rustc` flags.
+///
+/// Returns the flags for every crate, and the flags only applying to binaries: `panic` and `lto`
+/// have to agree across a whole crate graph, so they are left to final artifacts.
+pub fn profile_rustc_flags(profile: &TomlProfile) -> (Vec<String>, Vec<String>) {
+    let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+
+    let mut flags = Vec::new();
+    if let Some(opt_level) = &profile.opt_level {
+        flags.push(format!("-Copt-level={}", opt_level.0));
+    }
+    if let Some(debug) = &profile.debug {
+        flags.push(format!("-Cdebuginfo={}", debug));
+    }
+    if let Some(debug_assertions) = profile.debug_assertions {
+        flags.push(format!("-Cdebug-assertions={}", on_off(debug_assertions)));
+    }
+    if let Some(overflow_checks) = profile.overflow_checks {
+        flags.push(format!("-Coverflow-checks={}", on_off(overflow_checks)));
+    }
+    if let Some(codegen_units) = profile.codegen_units {
+        flags.push(format!("-Ccodegen-units={}", codegen_units));
+    }
+    if let Some(rustflags) = &profile.rustflags {
+        flags.extend(rustflags.iter().cloned());
+    }
+
+    let mut binary_flags = Vec::new();
+    match &profile.lto {
+        Some(StringOrBool::Bool(true)) => binary_flags.push("-Clto=fat".to_owned()),
+        Some(StringOrBool::String(lto)) if lto != "false" => {
+            binary_flags.push(format!("-Clto={}", lto))
+        }
+        _ => {}
+    }
+    if let Some(panic) = &profile.panic {
+        binary_flags.push(format!("-Cpanic={}", panic));
+    }
+    match &profile.strip {
+        Some(StringOrBool::Bool(strip)) => binary_flags.push(format!(
+            "-Cstrip={}",
+            if *strip { "symbols" } else { "none" }
+        )),
+        Some(StringOrBool::String(strip)) => binary_flags.push(format!("-Cstrip={}", strip)),
+        None => {}
+    }
+
+    (flags, binary_flags)
+}
+
+/// Generate the mode constraints of the custom profiles.
+pub fn write_profile_modes(
+    buck2_root: &Utf8Path,
+    profiles: &Map<String, TomlProfile>,
+) -> Result<()> {
+    let custom = custom_profiles(profiles);
+    let profiles_dir = buck2_root.join(PROFILES_PACKAGE);
+    if custom.is_empty() && !profiles_dir.exists() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(&profiles_dir)?;
+    let mut buck_file = std::fs::File::create(profiles_dir.join("BUCK"))?;
+
+    writeln!(buck_file, "# @generated by `cargo buckal`")?;
+    writeln!(buck_file, "#")?;
+    writeln!(
+        buck_file,
+        "# Modes of the custom Cargo profiles, selected with `-m <profile>`."
+    )?;
+    writeln!(buck_file)?;
+    writeln!(buck_file, "constraint_setting(")?;
+    writeln!(buck_file, "    name = \"profile\",")?;
+    writeln!(buck_file, "    visibility = [\"PUBLIC\"],")?;
+    writeln!(buck_file, ")")?;
+    for name in custom {
+        writeln!(buck_file)?;
+        writeln!(buck_file, "constraint_value(")?;
+        writeln!(buck_file, "    name = \"{}\",", name)?;
+        writeln!(buck_file, "    constraint_setting = \":profile\",")?;
+        writeln!(buck_file, "    visibility = [\"PUBLIC\"],")?;
+        writeln!(buck_file, ")")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo_util_schemas::manifest::TomlManifest;
+
+    fn parse_profiles(content: &str) -> Map<String, TomlProfile> {
+        let manifest: TomlManifest = toml::from_str(content).unwrap();
+        manifest
+            .profile
+            .unwrap()
+            .get_all()
+            .iter()
+            .map(|(name, profile)| (name.to_string(), profile.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_profile() {
+        let profiles = parse_profiles(
+            r#"
+            [profile.release]
+            lto = true
+            codegen-units = 1
+            panic = "abort"
+
+            [profile.ci]
+            inherits = "release"
+            debug = "line-tables-only"
+            strip = true
+
+            [profile.release.package.foo]
+            opt-level = "s"
+            "#,
+        );
+
+        let (flags, binary_flags) =
+            profile_rustc_flags(&resolve_profile("release", &profiles).unwrap());
+        assert_eq!(flags, vec!["-Ccodegen-units=1"]);
+        assert_eq!(binary_flags, vec!["-Clto=fat", "-Cpanic=abort"]);
+
+        let (flags, binary_flags) = profile_rustc_flags(&resolve_profile("ci", &profiles).unwrap());
+        assert_eq!(
+            flags,
+            vec![
+                "-Copt-level=3",
+                "-Cdebuginfo=line-tables-only",
+                "-Cdebug-assertions=off",
+                "-Coverflow-checks=off",
+                "-Ccodegen-units=1",
+            ]
+        );
+        assert_eq!(
+            binary_flags,
+            vec!["-Clto=fat", "-Cpanic=abort", "-Cstrip=symbols"]
+        );
+
+        assert!(resolve_profile("nightly", &profiles).is_err());
+        assert_eq!(custom_profiles(&profiles), vec!["ci"]);
+        assert_eq!(
+            profile_mode("ci", &profiles).unwrap(),
+            Some("ci".to_owned())
+        );
+        assert_eq!(profile_mode("dev", &profiles).unwrap(), None);
+        assert_eq!(
+            profile_mode("bench", &profiles).unwrap(),
+            Some("release".to_owned())
+        );
+        assert!(profile_mode("nightly", &profiles).is_err());
+
+        // A declared `bench` profile gets a mode of its own, on top of the release defaults
+        let profiles = parse_profiles(
+            r#"
+            [profile.bench]
+            debug = true
+            "#,
+        );
+        assert_eq!(custom_profiles(&profiles), vec!["bench"]);
+        assert_eq!(
+            profile_mode("bench", &profiles).unwrap(),
+            Some("bench".to_owned())
+        );
+        let (flags, _) = profile_rustc_flags(&resolve_profile("bench", &profiles).unwrap());
+        assert!(flags.contains(&"-Copt-level=3".to_owned()));
+        assert!(flags.contains(&"-Cdebuginfo=2".to_owned()));
+    }
+
+    #[test]
+    fn test_package_profile() {
+        let profiles = parse_profiles(
+            r#"
+            [profile.dev.package."*"]
+            opt-level = 2
+
+            [profile.dev.package.foo]
+            opt-level = 1
+            "#,
+        );
+        let package = |name: &str| -> Package {
+            serde_json::from_value(serde_json::json!({
+                "name": name,
+                "version": "0.1.0",
+                "id": format!("path+file:///tmp/{name}#0.1.0"),
+                "dependencies": [],
+                "targets": [],
+                "features": {},
+                "manifest_path": format!("/tmp/{name}/Cargo.toml"),
+                "edition": "2021"
+            }))
+            .unwrap()
+        };
+        let dev = resolve_profile("dev", &profiles).unwrap();
+
+        let opt_level = |profile: TomlProfile| profile.opt_level.map(|o| o.0);
+        assert_eq!(
+            opt_level(package_profile(&dev, &package("foo"), true)),
+            Some("1".to_owned())
+        );
+        assert_eq!(
+            opt_level(package_profile(&dev, &package("bar"), false)),
+            Some("2".to_owned())
+        );
+        assert_eq!(
+            opt_level(package_profile(&dev, &package("bar"), true)),
+            None
+        );
+    }
+}
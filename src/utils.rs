@@ -5,17 +5,21 @@ use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 use std::{io, process::Command, str::FromStr};
 
-use anyhow::{Result, bail};
-use cargo_metadata::camino::Utf8PathBuf;
-use cargo_metadata::{MetadataCommand, PackageId};
+use anyhow::{Context, Result, bail};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::{MetadataCommand, PackageId, Target};
 use cargo_platform::Cfg;
 use cargo_util_schemas::core::{PackageIdSpec, SourceKind};
+use cargo_util_schemas::lockfile::TomlLockfileSourceId;
+use cargo_util_schemas::manifest::TomlManifest;
 use colored::Colorize;
 use inquire::Select;
 
 use crate::buck2::Buck2Command;
 use crate::cache::BuckalCache;
-use crate::{RUST_CRATES_ROOT, RUST_GIT_ROOT};
+use crate::{
+    RUST_CRATES_ROOT, RUST_FIXUPS_ROOT, RUST_GIT_REPOS_ROOT, RUST_GIT_ROOT, RUST_PATH_ROOT,
+};
 
 #[macro_export]
 macro_rules! buckal_log {
@@ -30,6 +34,7 @@ macro_rules! buckal_log {
             "Logout" => ::colored::Colorize::green($action),
             "Push" => ::colored::Colorize::cyan($action),
             "Uploading" => ::colored::Colorize::green($action),
+            "Verified" => ::colored::Colorize::green($action),
             _ => ::colored::Colorize::blue($action),
         };
         println!("{:>12} {}", ::colored::Colorize::bold(colored), $msg);
@@ -317,6 +322,16 @@ pub fn ensure_buck2_installed() -> io::Result<()> {
     Ok(())
 }
 
+/// Load a `Cargo.toml` manifest.
+///
+/// `cargo metadata` does not expose every manifest key (e.g. `harness`, `[lints]` or `[profile]`),
+/// so some settings have to be read from the manifest itself.
+pub fn load_manifest(manifest_path: &Utf8Path) -> Result<TomlManifest> {
+    let content = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("failed to read `{}`", manifest_path))?;
+    toml::from_str(&content).with_context(|| format!("failed to parse `{}`", manifest_path))
+}
+
 /// Get the root directory of the Buck2 project by running `buck2 root --kind project`.
 pub fn get_buck2_root() -> Result<Utf8PathBuf> {
     static BUCK2_PROJECT_ROOT: OnceLock<Utf8PathBuf> = OnceLock::new();
@@ -443,7 +458,9 @@ pub fn get_cache_path() -> Result<Utf8PathBuf> {
 ///
 /// This function determines the vendor path based on the package source:
 /// - For registry packages, it returns `third-party/rust/crates/<package>/<version>`
-/// - For git packages, it returns `third-party/rust/git/<package>/<version>`
+/// - For git packages, it returns `third-party/rust/git/<package>/<version>-<source hash>`, so
+///   forks and revisions of a crate with the same version do not collide
+/// - For path packages, it returns `third-party/rust/path/<package>/<version>`
 pub fn get_vendor_path_relative(package_id: &PackageId) -> Result<String> {
     let package_id_spec = PackageIdSpec::parse(&package_id.repr)?;
     match package_id_spec
@@ -458,7 +475,15 @@ pub fn get_vendor_path_relative(package_id: &PackageId) -> Result<String> {
                 .expect("failed to extract package version")
         )),
         SourceKind::Git(_) => Ok(format!(
-            "{RUST_GIT_ROOT}/{}/{}",
+            "{RUST_GIT_ROOT}/{}/{}-{}",
+            package_id_spec.name(),
+            package_id_spec
+                .version()
+                .expect("failed to extract package version"),
+            git_source_hash(&package_id.repr)
+        )),
+        SourceKind::Path => Ok(format!(
+            "{RUST_PATH_ROOT}/{}/{}",
             package_id_spec.name(),
             package_id_spec
                 .version()
@@ -471,11 +496,60 @@ pub fn get_vendor_path_relative(package_id: &PackageId) -> Result<String> {
     }
 }
 
+/// Get a short hash of the git source of a package ID, with the branch, tag or rev it was
+/// requested at, which is all that tells apart git packages of the same name and version.
+fn git_source_hash(package_id: &str) -> String {
+    let source = package_id
+        .rsplit_once('#')
+        .map_or(package_id, |(source, _)| source);
+    blake3::hash(source.as_bytes()).to_hex()[..8].to_owned()
+}
+
+/// Get the repository URL and the revision of a git source
+pub fn parse_git_source(source: &str) -> Result<(String, String)> {
+    let source_id = TomlLockfileSourceId::new(source.to_owned())?;
+    let Some(rev) = source_id.url().fragment() else {
+        bail!("git source `{}` is not locked to a revision", source);
+    };
+    let mut repo = source_id.url().to_owned();
+    repo.set_fragment(None);
+    repo.set_query(None);
+    Ok((repo.to_string(), rev.to_owned()))
+}
+
+/// Get the path of the `git_fetch` rule shared by the crates of a git source, relative to the
+/// Buck2 project root: `third-party/rust/git/_repos/<hash of repository and revision>`
+pub fn get_git_repo_path_relative(source: &str) -> Result<String> {
+    let (repo, rev) = parse_git_source(source)?;
+    let hash = blake3::hash(format!("{}#{}", repo, rev).as_bytes()).to_hex();
+    Ok(format!("{RUST_GIT_REPOS_ROOT}/{}", &hash[..16]))
+}
+
+/// Get the `required-features` of a target that are not enabled in the resolved feature set
+///
+/// Only plain feature names are checked; `dep/feature` entries are assumed to be satisfied.
+pub fn unmet_required_features<F: AsRef<str>>(target: &Target, features: &[F]) -> Vec<String> {
+    target
+        .required_features
+        .iter()
+        .filter(|f| !f.contains('/') && !features.iter().any(|e| e.as_ref() == f.as_str()))
+        .cloned()
+        .collect()
+}
+
 /// Get the vendor directory for a given package
 pub fn get_vendor_dir(package_id: &PackageId) -> Result<Utf8PathBuf> {
     Ok(get_buck2_root()?.join(get_vendor_path_relative(package_id)?))
 }
 
+/// Get the path of the fixups file of a third-party crate, `third-party/rust/fixups/<crate>/fixups.toml`
+pub fn get_fixups_path(package_name: &str) -> Result<Utf8PathBuf> {
+    Ok(get_buck2_root()?
+        .join(RUST_FIXUPS_ROOT)
+        .join(package_name)
+        .join("fixups.toml"))
+}
+
 /// Retrieve the last saved BuckalCache from the cache file, or create a new one if the cache file does not exist.
 pub fn get_last_cache() -> BuckalCache {
     if let Ok(last_cache) = BuckalCache::load() {
@@ -666,4 +740,51 @@ mod tests {
             .expect("failed to read .gitignore");
         assert!(gitignore.contains("/buck-out"));
     }
+
+    #[test]
+    fn test_get_vendor_path_relative_git() {
+        let vendor_path = |repr: &str| {
+            get_vendor_path_relative(&PackageId {
+                repr: repr.to_owned(),
+            })
+            .unwrap()
+        };
+
+        let upstream = vendor_path("git+https://github.com/rust-lang/regex.git#regex@1.4.3");
+        assert!(upstream.starts_with("third-party/rust/git/regex/1.4.3-"));
+        assert_eq!(
+            upstream.len(),
+            "third-party/rust/git/regex/1.4.3-".len() + 8
+        );
+
+        // Forks and other revisions of the same version get their own directory
+        let fork = vendor_path("git+https://github.com/fork/regex.git#regex@1.4.3");
+        let branch =
+            vendor_path("git+https://github.com/rust-lang/regex.git?branch=dev#regex@1.4.3");
+        assert_ne!(upstream, fork);
+        assert_ne!(upstream, branch);
+        assert_eq!(
+            vendor_path("registry+https://github.com/rust-lang/crates.io-index#regex@1.4.3"),
+            "third-party/rust/crates/regex/1.4.3"
+        );
+    }
+
+    #[test]
+    fn test_get_git_repo_path_relative() {
+        let rev = "65b910d7571f36aa231958992e005a7a1c0838e9";
+        let repo = "https://github.com/web3infra-foundation/git-internal.git";
+        assert_eq!(
+            parse_git_source(&format!("git+{repo}?branch=main#{rev}")).unwrap(),
+            (repo.to_owned(), rev.to_owned())
+        );
+
+        // Crates of the same repository and revision share a path, whatever the requested ref
+        let path = get_git_repo_path_relative(&format!("git+{repo}#{rev}")).unwrap();
+        assert_eq!(
+            get_git_repo_path_relative(&format!("git+{repo}?branch=main#{rev}")).unwrap(),
+            path
+        );
+        assert_eq!(path, "third-party/rust/git/_repos/9c150773e8e7cf19");
+        assert!(get_git_repo_path_relative(&format!("git+{repo}?branch=main")).is_err());
+    }
 }
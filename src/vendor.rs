@@ -0,0 +1,364 @@
+use std::{collections::BTreeMap as Map, fs::File};
+
+use anyhow::{Context, Result, anyhow, bail};
+use cargo_metadata::{
+    Package,
+    camino::{Utf8Path, Utf8PathBuf},
+};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::cargo_config::cargo_home;
+
+/// Checksums of vendored sources, in the format of `cargo vendor`
+const CHECKSUM_FILE: &str = ".cargo-checksum.json";
+
+/// Directory in `vendor_dir` archives are unpacked into, before their entries are moved out
+const UNPACK_STAGING_DIR: &str = ".unpack";
+
+#[derive(Serialize, Deserialize, Default)]
+struct CargoChecksum {
+    #[serde(default)]
+    files: Map<String, String>,
+    package: Option<String>,
+}
+
+/// Remove everything in `vendor_dir` but its BUCK file.
+fn clear_vendor_dir(vendor_dir: &Utf8Path) -> Result<()> {
+    for entry in std::fs::read_dir(vendor_dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name == "BUCK") {
+            continue;
+        }
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace the sources in `vendor_dir` with those of a path package, keeping the BUCK file.
+pub fn copy_path_sources(package_dir: &Utf8Path, vendor_dir: &Utf8Path) -> Result<()> {
+    clear_vendor_dir(vendor_dir)?;
+
+    let walker = WalkDir::new(package_dir)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            // Skip build outputs, VCS metadata and the BUCK file of the package itself
+            let name = entry.file_name();
+            !(entry.depth() == 1 && (name == "target" || name == "BUCK")) && name != ".git"
+        });
+    for entry in walker {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(package_dir)?;
+        let dest = vendor_dir.as_std_path().join(relative);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&dest)?;
+        } else {
+            std::fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the sources of a git package from its Cargo checkout into `vendor_dir`, next to its BUCK
+/// file.
+pub fn vendor_git_package(package: &Package, vendor_dir: &Utf8Path) -> Result<()> {
+    let package_dir = package.manifest_path.parent().unwrap();
+    if !package_dir.is_dir() {
+        bail!(
+            "the git checkout `{}` is missing, run `cargo fetch` first",
+            package_dir
+        );
+    }
+    copy_path_sources(package_dir, vendor_dir)
+}
+
+/// Unpack the sources of a registry package into `vendor_dir`, next to its BUCK file.
+///
+/// Sources are taken from the `cargo vendor` directory if given, otherwise from the `.crate`
+/// archives downloaded by Cargo, and are verified against the `checksum` from `Cargo.lock`.
+/// Sources already vendored with the same checksum are left untouched.
+pub fn vendor_crate(
+    package: &Package,
+    checksum: &str,
+    cargo_vendor_dir: Option<&Utf8Path>,
+    vendor_dir: &Utf8Path,
+) -> Result<()> {
+    if vendored_checksum(vendor_dir).as_deref() == Some(checksum) {
+        return Ok(());
+    }
+    clear_vendor_dir(vendor_dir)?;
+
+    if let Some(cargo_vendor_dir) = cargo_vendor_dir {
+        let source_dir = find_cargo_vendor_sources(cargo_vendor_dir, package, checksum)?;
+        verify_cargo_vendor_sources(&source_dir)?;
+        copy_path_sources(&source_dir, vendor_dir)
+    } else {
+        let crate_file = find_cached_crate(package, checksum)?;
+        unpack_crate(
+            &crate_file,
+            &format!("{}-{}", package.name, package.version),
+            vendor_dir,
+        )?;
+        let checksums = CargoChecksum {
+            files: hash_files(vendor_dir)?,
+            package: Some(checksum.to_owned()),
+        };
+        std::fs::write(
+            vendor_dir.join(CHECKSUM_FILE),
+            serde_json::to_string(&checksums)?,
+        )?;
+        Ok(())
+    }
+}
+
+fn read_checksum_file(dir: &Utf8Path) -> Option<CargoChecksum> {
+    let content = std::fs::read_to_string(dir.join(CHECKSUM_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Get the package checksum recorded with vendored sources, if any.
+pub fn vendored_checksum(dir: &Utf8Path) -> Option<String> {
+    read_checksum_file(dir)?.package
+}
+
+/// Hash the files of vendored sources, keyed by their path relative to `dir` like `cargo vendor`.
+fn hash_files(dir: &Utf8Path) -> Result<Map<String, String>> {
+    let mut files = Map::new();
+    for entry in WalkDir::new(dir).min_depth(1) {
+        let entry = entry?;
+        if !entry.file_type().is_file() || is_generated(entry.depth(), entry.file_name()) {
+            continue;
+        }
+        let path = Utf8Path::from_path(entry.path())
+            .ok_or_else(|| anyhow!("non UTF-8 path `{}`", entry.path().display()))?;
+        let relative = path.strip_prefix(dir)?.as_str().replace('\\', "/");
+        files.insert(relative, sha256_file(path)?);
+    }
+    Ok(files)
+}
+
+/// Whether the entry at the given depth of a vendor directory is generated rather than vendored.
+fn is_generated(depth: usize, name: &std::ffi::OsStr) -> bool {
+    depth == 1 && (name == "BUCK" || name == CHECKSUM_FILE || name == UNPACK_STAGING_DIR)
+}
+
+fn sha256_file(path: &Utf8Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Find the sources of the package in a `cargo vendor` directory, either versioned or not.
+fn find_cargo_vendor_sources(
+    cargo_vendor_dir: &Utf8Path,
+    package: &Package,
+    checksum: &str,
+) -> Result<Utf8PathBuf> {
+    [
+        cargo_vendor_dir.join(format!("{}-{}", package.name, package.version)),
+        cargo_vendor_dir.join(package.name.as_str()),
+    ]
+    .into_iter()
+    .find(|dir| vendored_checksum(dir).as_deref() == Some(checksum))
+    .ok_or_else(|| {
+        anyhow!(
+            "`{} v{}` with checksum {} is not vendored in `{}`",
+            package.name,
+            package.version,
+            checksum,
+            cargo_vendor_dir
+        )
+    })
+}
+
+/// Check the files of vendored sources against their `.cargo-checksum.json`.
+///
+/// The BUCK file of the package is replaced by the generated one, so it is not checked. Files
+/// missing from the checksums, such as sources added after vendoring, are reported too.
+pub fn verify_cargo_vendor_sources(source_dir: &Utf8Path) -> Result<()> {
+    let checksums = read_checksum_file(source_dir)
+        .ok_or_else(|| anyhow!("missing `{}` in `{}`", CHECKSUM_FILE, source_dir))?;
+    if checksums.files.is_empty() {
+        bail!("no file checksums recorded in `{}`", source_dir);
+    }
+    let actual_files = hash_files(source_dir)
+        .with_context(|| format!("failed to hash the files of `{}`", source_dir))?;
+    for (file, expected) in &checksums.files {
+        if file == "BUCK" {
+            continue;
+        }
+        match actual_files.get(file) {
+            Some(actual) if actual == expected => {}
+            Some(_) => bail!("checksum mismatch of `{}` in `{}`", file, source_dir),
+            None => bail!("missing `{}` in `{}`", file, source_dir),
+        }
+    }
+    if let Some(file) = actual_files
+        .keys()
+        .find(|file| !checksums.files.contains_key(*file))
+    {
+        bail!("unexpected file `{}` in `{}`", file, source_dir);
+    }
+    Ok(())
+}
+
+/// Find the `.crate` archive of the package downloaded by Cargo, matching the checksum.
+fn find_cached_crate(package: &Package, checksum: &str) -> Result<Utf8PathBuf> {
+    let cache_dir = cargo_home().join("registry").join("cache");
+    let file_name = format!("{}-{}.crate", package.name, package.version);
+    for entry in
+        std::fs::read_dir(&cache_dir).with_context(|| format!("failed to read `{}`", cache_dir))?
+    {
+        let Ok(index_dir) = Utf8PathBuf::from_path_buf(entry?.path()) else {
+            continue;
+        };
+        let crate_file = index_dir.join(&file_name);
+        if crate_file.is_file() && sha256_file(&crate_file)? == checksum {
+            return Ok(crate_file);
+        }
+    }
+    bail!(
+        "`{}` with checksum {} is not in the Cargo cache, run `cargo fetch` first",
+        file_name,
+        checksum
+    )
+}
+
+/// Unpack a `.crate` archive, stripping the `<name>-<version>` directory of its entries.
+///
+/// Entries are unpacked into a staging directory with [`tar::Entry::unpack_in`], which refuses
+/// paths escaping it, then moved into `dest`.
+fn unpack_crate(crate_file: &Utf8Path, prefix: &str, dest: &Utf8Path) -> Result<()> {
+    let staging = dest.join(UNPACK_STAGING_DIR);
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(crate_file)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !path.starts_with(prefix) {
+            bail!("unexpected entry `{}` in `{}`", path.display(), crate_file);
+        }
+        if !entry.unpack_in(&staging)? {
+            bail!(
+                "entry `{}` of `{}` escapes its package",
+                path.display(),
+                crate_file
+            );
+        }
+    }
+
+    let unpacked = staging.join(prefix);
+    if unpacked.is_dir() {
+        for entry in std::fs::read_dir(&unpacked)? {
+            let entry = entry?;
+            // The BUCK file of the package would replace the generated one
+            if entry.file_name() == "BUCK" {
+                continue;
+            }
+            std::fs::rename(entry.path(), dest.as_std_path().join(entry.file_name()))?;
+        }
+    }
+    std::fs::remove_dir_all(&staging)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_path_sources() {
+        let package_dir = tempfile::tempdir().unwrap();
+        let vendor_dir = tempfile::tempdir().unwrap();
+        let package_path = Utf8Path::from_path(package_dir.path()).unwrap();
+        let vendor_path = Utf8Path::from_path(vendor_dir.path()).unwrap();
+
+        std::fs::create_dir_all(package_path.join("src")).unwrap();
+        std::fs::create_dir_all(package_path.join("target/debug")).unwrap();
+        std::fs::write(package_path.join("Cargo.toml"), "[package]").unwrap();
+        std::fs::write(package_path.join("src/lib.rs"), "").unwrap();
+        std::fs::write(package_path.join("target/debug/foo"), "").unwrap();
+        std::fs::write(vendor_path.join("BUCK"), "# manual changes").unwrap();
+        std::fs::write(vendor_path.join("stale.rs"), "").unwrap();
+
+        copy_path_sources(package_path, vendor_path).unwrap();
+
+        assert!(vendor_path.join("Cargo.toml").is_file());
+        assert!(vendor_path.join("src/lib.rs").is_file());
+        assert!(!vendor_path.join("target").exists());
+        assert!(!vendor_path.join("stale.rs").exists());
+        assert_eq!(
+            std::fs::read_to_string(vendor_path.join("BUCK")).unwrap(),
+            "# manual changes"
+        );
+    }
+
+    #[test]
+    fn test_unpack_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+
+        // Build a `.crate` archive like `cargo package` does
+        let crate_file = root.join("demo-0.1.0.crate");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&crate_file).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (path, content) in [
+            ("demo-0.1.0/Cargo.toml", "[package]\n"),
+            ("demo-0.1.0/src/lib.rs", "pub fn demo() {}\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let dest = root.join("vendor");
+        std::fs::create_dir_all(&dest).unwrap();
+        std::fs::write(dest.join("BUCK"), "# generated").unwrap();
+        unpack_crate(&crate_file, "demo-0.1.0", &dest).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dest.join("src/lib.rs")).unwrap(),
+            "pub fn demo() {}\n"
+        );
+        assert!(!dest.join(UNPACK_STAGING_DIR).exists());
+
+        // Vendored sources are verified against the checksums of their files
+        let files = hash_files(&dest).unwrap();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            vec!["Cargo.toml", "src/lib.rs"]
+        );
+        let checksums = CargoChecksum {
+            files,
+            package: Some(sha256_file(&crate_file).unwrap()),
+        };
+        std::fs::write(
+            dest.join(CHECKSUM_FILE),
+            serde_json::to_string(&checksums).unwrap(),
+        )
+        .unwrap();
+        verify_cargo_vendor_sources(&dest).unwrap();
+
+        std::fs::write(dest.join("src/lib.rs"), "tampered").unwrap();
+        assert!(verify_cargo_vendor_sources(&dest).is_err());
+    }
+}